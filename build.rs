@@ -24,11 +24,11 @@ fn uuid() -> Result<String, Box<dyn Error>> {
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-  println!("cargo:rustc-env=SAD_ARGV_UUID={uuid}", uuid = uuid()?);
-
   println!("cargo:rustc-env=SAD_PREVIEW_UUID={uuid}", uuid = uuid()?);
 
   println!("cargo:rustc-env=SAD_PATCH_UUID={uuid}", uuid = uuid()?);
 
+  println!("cargo:rustc-env=SAD_RELOAD_UUID={uuid}", uuid = uuid()?);
+
   Ok(())
 }
@@ -1,43 +1,91 @@
 use {
-  super::{subprocess::SubprocCommand, types::Die},
-  aho_corasick::{AhoCorasick, AhoCorasickBuilder},
-  clap::Parser,
-  regex::{Regex, RegexBuilder},
+  super::{
+    script::{p_script, Script},
+    subprocess::{Coprocess, SubprocCommand, REPLY_TIMEOUT},
+    template::{p_group, p_template, Group, Template},
+    types::Die,
+  },
+  aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind},
+  clap::{error::ErrorKind as ClapError, CommandFactory, Parser, ValueEnum},
+  fancy_regex::{Regex as FancyRegex, RegexBuilder as FancyRegexBuilder},
+  regex::{Error as RegexError, Regex, RegexBuilder},
+  serde_json::from_str,
   shlex::split,
   std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     env::{args_os, current_dir, var_os},
     ffi::OsString,
+    fs::read_to_string,
     io::{stderr, stdout, IsTerminal},
-    path::PathBuf,
+    mem::take,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
   },
   which::which,
 };
 
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Mode {
-  Initial,
-  Preview(PathBuf),
-  Patch(PathBuf),
+  Initial(Arguments),
+  Preview(PathBuf, PathBuf),
+  Patch(PathBuf, PathBuf),
+  Reload(PathBuf, OsString),
 }
 
 impl Mode {
-  pub const ARGV: &'static str = env!("SAD_ARGV_UUID");
   pub const PREVIEW: &'static str = env!("SAD_PREVIEW_UUID");
   pub const PATCH: &'static str = env!("SAD_PATCH_UUID");
+  pub const RELOAD: &'static str = env!("SAD_RELOAD_UUID");
 }
 
 #[derive(Debug, Parser)]
 #[clap(about, version)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Arguments {
   /// Search pattern
-  #[clap()]
-  pub pattern: String,
+  #[clap(required_unless_present_any = ["undo", "history", "exprs", "dictionary"])]
+  pub pattern: Option<String>,
 
   /// Replacement pattern, empty = delete
+  ///
+  /// `$1` `${name}` :: captures, `$$` :: literal '$'
+  ///
+  /// `${#}` `${#START,STEP}` :: match counter per file, `${##}` :: across files, `${1*2+1:04}` :: arithmetic & padding
+  ///
+  /// `${FILE}` `${STEM}` `${EXT}` `${LINE}` :: where the match is, `${ENV:X}` :: environment
+  ///
+  /// `${map:FILE:$1}` :: look `$1` up in a `--dictionary` style table, misses are left alone
+  ///
+  /// `\U` `\L` :: upper / lower case until `\E`, `\u` `\l` :: the next character only
   #[clap()]
   pub replace: Option<String>,
 
+  /// Files or directories to walk recursively
+  ///
+  /// Defaults to `.` when stdin is a tty, otherwise paths are read from stdin
+  #[clap()]
+  pub paths: Vec<PathBuf>,
+
+  /// Additional expression, applied in order, repeatable
+  ///
+  /// With `--expr`, all positional arguments are paths, a positional pattern is refused
+  ///
+  /// Per-expression flags go in a leading group, ie. `(?i)pattern`
+  ///
+  /// With `--exact`, all pairs are replaced at once, in a single pass
+  #[clap(long = "expr", num_args = 2, value_names = ["PATTERN", "REPLACE"])]
+  pub exprs: Vec<String>,
+
+  /// Replace every KEY with its VALUE from a `KEY<tab>VALUE` (or `.json` object) file, in one pass
+  ///
+  /// Keys are literal and case sensitive unless `-f i`, the longest match wins, ie. swaps like foo <-> bar are safe
+  ///
+  /// With `--dictionary`, all positional arguments are paths, a positional pattern is refused
+  #[clap(long, value_name = "FILE")]
+  pub dictionary: Option<PathBuf>,
+
   /// Use \0 as stdin delimiter
   #[clap(short = '0', long)]
   pub read0: bool,
@@ -46,14 +94,130 @@ pub struct Arguments {
   #[clap(short = 'k', long)]
   pub commit: bool,
 
+  /// Prompt for each hunk on the terminal, then write accepted changes
+  ///
+  /// y :: apply hunk, n :: skip hunk, a :: apply rest of file, d :: skip rest of file, q :: quit
+  ///
+  /// e :: edit hunk in `$VISUAL` / `$EDITOR`, rejected if it no longer matches the file
+  #[clap(short, long, conflicts_with_all = ["commit", "fzf"])]
+  pub interactive: bool,
+
+  /// Select individual matches instead of diff hunks
+  ///
+  /// ie. one of several matches on the same line can be skipped
+  #[clap(long)]
+  pub per_match: bool,
+
   /// String literal mode
   #[clap(short, long)]
   pub exact: bool,
 
+  /// Regex engine, `fancy` adds look-around and backreferences, ie. `foo(?!\()`
+  ///
+  /// `fancy` backtracks, prefer the default unless the pattern needs it
+  #[clap(
+    long,
+    value_enum,
+    value_name = "ENGINE",
+    default_value = "regex",
+    conflicts_with = "exact"
+  )]
+  pub engine: Dialect,
+
+  /// Literal identifier mode, also rewrites the other case styles of the pattern
+  ///
+  /// ie. `user_id account_id` also turns `UserId` into `AccountId`, `USER_ID` into `ACCOUNT_ID`,
+  /// `userId` into `accountId` and `user-id` into `account-id`
+  #[clap(long, conflicts_with = "engine")]
+  pub preserve_case: bool,
+
+  /// Compute each replacement with `fn replace(groups, file, line)` from a Rhai script
+  ///
+  /// `groups` maps capture indices and names to text, return `()` to leave the match as is
+  ///
+  /// Top level statements run once, `print` and `debug` go to stderr
+  ///
+  /// With `--script`, there is no replacement argument, all positional arguments after the pattern are paths
+  #[clap(
+    long,
+    value_name = "FILE",
+    conflicts_with_all = ["exact", "engine", "preserve_case", "exprs", "dictionary", "live"]
+  )]
+  pub script: Option<PathBuf>,
+
+  /// Compute each replacement with a long-lived command, ie. `--replace-cmd 'python3 fix.py'`
+  ///
+  /// Every match is written to its stdin as a JSON string on one line,
+  /// it must answer with one line per match: a JSON string, or `null` to leave the match as is
+  ///
+  /// Answers must be flushed and arrive within 10 seconds, or the command is killed
+  ///
+  /// With `--replace-cmd`, there is no replacement argument, all positional arguments after the pattern are paths
+  #[clap(
+    long,
+    value_name = "COMMAND",
+    conflicts_with_all = ["exact", "engine", "preserve_case", "exprs", "dictionary", "live", "script"]
+  )]
+  pub replace_cmd: Option<String>,
+
+  /// Only rewrite this capture group, by index or name, the rest of the match is kept as is
+  ///
+  /// ie. `'version = "(\d+)"' 2 --group 1`
+  #[clap(
+    long,
+    value_name = "GROUP",
+    conflicts_with_all = ["exact", "preserve_case", "dictionary"]
+  )]
+  pub group: Option<String>,
+
+  /// Only replace the Nth match on each line, like sed's `s/a/b/N`
+  ///
+  /// `--occurrence` `--max-per-file` `--max-total` count only matches that change something
+  #[clap(long, value_name = "N")]
+  pub occurrence: Option<usize>,
+
+  /// Replace at most N matches per file
+  #[clap(long, value_name = "N")]
+  pub max_per_file: Option<usize>,
+
+  /// Replace at most N matches in total, earlier files first, files are then read one at a time
+  #[clap(long, value_name = "N")]
+  pub max_total: Option<usize>,
+
+  /// Only replace within these lines, `10:40` `10:` `:40`, `!` to exclude, repeatable
+  #[clap(long, value_name = "RANGE")]
+  pub lines: Vec<String>,
+
+  /// Only replace between lines matching START and END, markers excluded, repeatable
+  #[clap(long, num_args = 2, value_names = ["START", "END"])]
+  pub between: Vec<String>,
+
+  /// Never replace between lines matching START and END, markers included, repeatable
+  #[clap(long, num_args = 2, value_names = ["START", "END"])]
+  pub outside: Vec<String>,
+
+  /// Only touch files that contain a match for this regex
+  #[clap(long, value_name = "REGEX")]
+  pub if_contains: Option<String>,
+
+  /// Only touch files that do not contain a match for this regex
+  #[clap(long, value_name = "REGEX")]
+  pub unless_contains: Option<String>,
+
+  /// Only replace on lines matching this regex
+  #[clap(long, value_name = "REGEX")]
+  pub if_line: Option<String>,
+
+  /// Never replace on lines matching this regex
+  #[clap(long, value_name = "REGEX")]
+  pub unless_line: Option<String>,
+
   /// Regex flags: use `--help` instead of `-h` to see details
   ///
   /// [lowercase on, uppercase off] ie i => on, I => off
   ///
+  /// Also applies to `--if-line` `--between` `--if-contains` & co
+  ///
   /// i :: ignore case (works for --exact)
   ///
   /// m :: multiline '^', '$'
@@ -66,7 +230,7 @@ pub struct Arguments {
   #[clap(short, long)]
   pub flags: Option<String>,
 
-  /// Colourizing program, disable = never, default = $GIT_PAGER
+  /// Colourizing program, disable = never, default = `$GIT_PAGER`
   ///
   /// Uses bash shell syntax for splitting
   #[clap(short, long)]
@@ -74,49 +238,127 @@ pub struct Arguments {
 
   /// Additional Fzf options, disable = never
   ///
+  /// Without fzf, a built-in picker is used instead, also disabled by never
+  ///
   /// Uses bash shell syntax for splitting
   #[clap(long)]
   pub fzf: Option<String>,
 
+  /// Edit the pattern (or replace) from the fzf query, re-running on every keystroke
+  #[clap(
+    long,
+    value_enum,
+    value_name = "FIELD",
+    num_args = 0..=1,
+    default_missing_value = "pattern",
+    conflicts_with_all = ["commit", "interactive", "exprs", "dictionary"]
+  )]
+  pub live: Option<Live>,
+
   /// Same as in GNU diff --unified={size}, affects aggregate size
   ///
   /// ie. a higher {size} will leader to more changes grouped together
   #[clap(short, long)]
   pub unified: Option<usize>,
+
+  /// Walk hidden files and directories
+  #[clap(long)]
+  pub hidden: bool,
+
+  /// Do not respect `.gitignore`, `.ignore` and friends
+  #[clap(long)]
+  pub no_ignore: bool,
+
+  /// Include files matching glob, `!` prefix to exclude, repeatable
+  ///
+  /// Globs override `.gitignore` and friends, but not hidden files
+  #[clap(short, long)]
+  pub glob: Vec<String>,
+
+  /// Include files of type (ripgrep types), `!` prefix to exclude, repeatable
+  #[clap(short = 't', long = "type")]
+  pub file_type: Vec<String>,
+
+  /// Descend at most {depth} directories below the given paths
+  #[clap(long)]
+  pub max_depth: Option<usize>,
+
+  /// Restore files written by a previous `--commit`, default = latest run
+  ///
+  /// Refuses if any of the files have changed since
+  #[clap(long, value_name = "RUN_ID", conflicts_with = "history")]
+  #[allow(clippy::option_option)]
+  pub undo: Option<Option<String>>,
+
+  /// List previous `--commit` runs
+  #[clap(long)]
+  pub history: bool,
+
+  /// Do not journal this `--commit` run, it cannot be undone
+  #[clap(long)]
+  pub no_journal: bool,
 }
 
-fn parse_fzf_mode(argv: &OsString) -> Option<Mode> {
-  let exec = argv
-    .to_str()
-    .unwrap_or_default()
-    .split('\x04')
-    .collect::<Vec<_>>();
-  match exec[..] {
-    [Mode::PREVIEW, path] => Some(Mode::Preview(PathBuf::from(path))),
-    [Mode::PATCH, path] => Some(Mode::Patch(PathBuf::from(path))),
-    _ => None,
+/// Move positional arguments that are paths out of the pattern & replacement slots
+pub fn p_positionals(args: &mut Arguments) -> Result<(), String> {
+  if !args.exprs.is_empty() || args.dictionary.is_some() {
+    let mut paths = (args.pattern.take().into_iter())
+      .chain(args.replace.take())
+      .map(PathBuf::from)
+      .collect::<Vec<_>>();
+    if let Some(path) = paths.iter().find(|path| !path.exists()) {
+      return Err(format!(
+        "with --expr or --dictionary, positional arguments are paths, pass pairs as --expr PATTERN REPLACE :: {}",
+        path.display()
+      ));
+    }
+    paths.append(&mut args.paths);
+    args.paths = paths;
+  } else if args.script.is_some() || args.replace_cmd.is_some() {
+    if let Some(replace) = args.replace.take() {
+      args.paths.insert(0, PathBuf::from(replace));
+    }
   }
+  Ok(())
 }
 
-pub fn parse_args() -> (Mode, Arguments) {
+pub fn parse_args() -> Mode {
   let args = args_os().collect::<Vec<_>>();
   match (
     args.get(1).and_then(|a| a.to_str()),
-    args.get(2).and_then(parse_fzf_mode),
-    var_os(Mode::ARGV).and_then(|a| a.into_string().ok()),
+    args.get(2),
+    args.get(3),
+    args.len(),
   ) {
-    (Some("-c"), Some(mode), Some(arg_list)) => {
-      (mode, Arguments::parse_from(arg_list.split('\x04')))
+    (Some(Mode::PREVIEW), Some(socket), Some(selection), 4) => {
+      Mode::Preview(PathBuf::from(socket), PathBuf::from(selection))
+    }
+    (Some(Mode::PATCH), Some(socket), Some(selection), 4) => {
+      Mode::Patch(PathBuf::from(socket), PathBuf::from(selection))
+    }
+    (Some(Mode::RELOAD), Some(socket), Some(query), 4) => {
+      Mode::Reload(PathBuf::from(socket), query.clone())
+    }
+    _ => {
+      let mut args = Arguments::parse_from(args);
+      if let Err(e) = p_positionals(&mut args) {
+        Arguments::command()
+          .error(ClapError::ArgumentConflict, e)
+          .exit();
+      }
+      Mode::Initial(args)
     }
-    _ => (Mode::Initial, Arguments::parse_from(args)),
   }
 }
 
 #[derive(Clone, Debug)]
 #[allow(clippy::large_enum_variant)]
 pub enum Engine {
-  AhoCorasick(AhoCorasick, String),
-  Regex(Regex, String),
+  AhoCorasick(AhoCorasick, Vec<String>),
+  Regex(Regex, Template),
+  Fancy(FancyRegex, Template),
+  Script(Regex, Arc<Script>),
+  Command(Regex, Arc<Coprocess>),
 }
 
 #[derive(Clone, Debug)]
@@ -124,6 +366,103 @@ pub enum Action {
   Preview,
   Commit,
   FzfPreview(PathBuf, Vec<String>),
+  Tui,
+  Interactive,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Dialect {
+  Regex,
+  Fancy,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Live {
+  Pattern,
+  Replace,
+}
+
+/// `--lines` `--between` `--outside` `--if-line` `--unless-line`
+#[derive(Clone, Debug, Default)]
+pub struct Address {
+  pub lines: Vec<(bool, RangeInclusive<usize>)>,
+  pub between: Vec<(Regex, Regex)>,
+  pub outside: Vec<(Regex, Regex)>,
+  pub if_line: Option<Regex>,
+  pub unless_line: Option<Regex>,
+}
+
+impl Address {
+  pub const fn is_empty(&self) -> bool {
+    self.lines.is_empty()
+      && self.between.is_empty()
+      && self.outside.is_empty()
+      && self.if_line.is_none()
+      && self.unless_line.is_none()
+  }
+}
+
+/// Matches replaced so far, a file keeps its share across re-runs, ie. preview then commit
+#[derive(Debug, Default)]
+struct Books {
+  used: usize,
+  totals: HashMap<usize, usize>,
+  by_file: HashMap<(PathBuf, usize), (usize, usize)>,
+}
+
+/// Counts across files for `--max-total` and `${##}`, files must be displaced in walk order
+#[derive(Debug)]
+pub struct Ledger {
+  limit: Option<usize>,
+  books: Mutex<Books>,
+}
+
+impl Ledger {
+  fn new(limit: Option<usize>, engines: &[Engine]) -> Option<Arc<Self>> {
+    let totals = engines.iter().any(|engine| match engine {
+      Engine::Regex(_, template) | Engine::Fancy(_, template) => template.has_total(),
+      _ => false,
+    });
+    (limit.is_some() || totals).then(|| {
+      Arc::new(Self {
+        limit,
+        books: Mutex::new(Books::default()),
+      })
+    })
+  }
+
+  /// `${##}` offset and room left for the `nth` engine in `path`
+  pub fn open(&self, path: &Path, nth: usize) -> (usize, usize) {
+    let books = self.books.lock().expect("ledger lock");
+    let opened = books.by_file.get(&(path.to_owned(), nth)).map_or_else(
+      || {
+        let base = books.totals.get(&nth).copied().unwrap_or_default();
+        let room = self.limit.map_or(usize::MAX, |limit| limit - books.used);
+        (base, room)
+      },
+      |&(base, kept)| (base, kept),
+    );
+    drop(books);
+    opened
+  }
+
+  /// Book the `kept` matches, only the first run of a file counts
+  pub fn close(&self, path: &Path, nth: usize, kept: usize) {
+    let mut books = self.books.lock().expect("ledger lock");
+    let Books {
+      used,
+      totals,
+      by_file,
+    } = &mut *books;
+    by_file.entry((path.to_owned(), nth)).or_insert_with(|| {
+      let base = totals.entry(nth).or_default();
+      let opened = (*base, kept);
+      *base += kept;
+      *used += kept;
+      opened
+    });
+    drop(books);
+  }
 }
 
 #[derive(Clone, Debug)]
@@ -133,12 +472,87 @@ pub enum Printer {
 }
 
 #[derive(Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Options {
   pub cwd: Option<PathBuf>,
+  pub exprs: Vec<(String, String)>,
   pub action: Action,
-  pub engine: Engine,
+  pub engines: Vec<Engine>,
   pub printer: Printer,
   pub unified: usize,
+  pub per_match: bool,
+  pub exact: bool,
+  pub dialect: Dialect,
+  pub preserve_case: bool,
+  pub group: Group,
+  pub address: Address,
+  pub if_contains: Option<Regex>,
+  pub unless_contains: Option<Regex>,
+  pub occurrence: Option<usize>,
+  pub max_per_file: Option<usize>,
+  pub max_total: Option<usize>,
+  pub ledger: Option<Arc<Ledger>>,
+  pub flags: Option<String>,
+  pub live: Option<Live>,
+  pub journal: bool,
+}
+
+impl Engine {
+  fn has_group(&self, group: &Group) -> bool {
+    match (self, group) {
+      (_, Group::Index(0)) => true,
+      (Self::AhoCorasick(..), _) => false,
+      (Self::Regex(re, _) | Self::Script(re, _) | Self::Command(re, _), Group::Index(idx)) => {
+        *idx < re.captures_len()
+      }
+      (Self::Regex(re, _) | Self::Script(re, _) | Self::Command(re, _), Group::Name(name)) => {
+        re.capture_names().flatten().any(|n| n == name)
+      }
+      (Self::Fancy(re, _), Group::Index(idx)) => *idx < re.captures_len(),
+      (Self::Fancy(re, _), Group::Name(name)) => re.capture_names().flatten().any(|n| n == name),
+    }
+  }
+}
+
+impl Options {
+  /// How many files to displace at once, `--max-total` and `${##}` go one by one in walk order
+  pub const fn lanes(&self, threads: usize) -> usize {
+    if self.ledger.is_some() {
+      1
+    } else {
+      threads
+    }
+  }
+
+  pub fn with_query(&self, query: &str) -> Result<Self, Die> {
+    let (pattern, replace) = self.exprs.first().cloned().unwrap_or_default();
+    let (pattern, replace) = match self.live {
+      Some(Live::Replace) => (pattern, query.to_owned()),
+      _ => (query.to_owned(), replace),
+    };
+    let engine = if self.preserve_case {
+      p_lexicon(
+        &p_variants(&pattern, &replace),
+        false,
+        self.flags.as_deref(),
+      )?
+    } else {
+      p_engine(
+        &pattern,
+        &replace,
+        self.exact,
+        self.dialect,
+        self.flags.as_deref(),
+      )?
+    };
+    let engines = vec![engine];
+    Ok(Self {
+      exprs: vec![(pattern, replace)],
+      ledger: Ledger::new(self.max_total, &engines),
+      engines,
+      ..self.clone()
+    })
+  }
 }
 
 fn p_auto_flags(exact: bool, pattern: &str) -> Vec<String> {
@@ -155,8 +569,9 @@ fn p_auto_flags(exact: bool, pattern: &str) -> Vec<String> {
   flags
 }
 
-fn p_aho_corasick(pattern: &str, flags: Vec<String>) -> Result<AhoCorasick, Die> {
+fn p_aho_corasick(patterns: &[&str], flags: Vec<String>) -> Result<AhoCorasick, Die> {
   let mut ac = AhoCorasickBuilder::new();
+  ac.match_kind(MatchKind::LeftmostLongest);
   for flag in flags {
     match flag.as_str() {
       "i" => ac.ascii_case_insensitive(true),
@@ -168,7 +583,7 @@ fn p_aho_corasick(pattern: &str, flags: Vec<String>) -> Result<AhoCorasick, Die>
       }
     };
   }
-  Ok(ac.build([pattern])?)
+  Ok(ac.build(patterns)?)
 }
 
 fn p_regex(pattern: &str, flags: Vec<String>) -> Result<Regex, Die> {
@@ -192,24 +607,76 @@ fn p_regex(pattern: &str, flags: Vec<String>) -> Result<Regex, Die> {
       }
     };
   }
+  re.build().map_err(|e| match &e {
+    RegexError::Syntax(msg) if msg.contains("look-around") || msg.contains("backreferences") => {
+      Die::ArgumentError(format!("{e}\nThis pattern needs `--engine fancy`"))
+    }
+    _ => Die::RegexError(e),
+  })
+}
+
+fn p_fancy(pattern: &str, flags: Vec<String>) -> Result<FancyRegex, Die> {
+  let mut swap = false;
+  let mut re = FancyRegexBuilder::new("");
+  for flag in flags {
+    match flag.as_str() {
+      "i" => re.case_insensitive(true),
+      "I" => re.case_insensitive(false),
+      "m" => re.multi_line(true),
+      "M" => re.multi_line(false),
+      "s" => re.dot_matches_new_line(true),
+      "S" => re.dot_matches_new_line(false),
+      "u" | "U" => {
+        swap = flag == "u";
+        &mut re
+      }
+      "x" => re.ignore_whitespace(true),
+      "X" => re.ignore_whitespace(false),
+      _ => {
+        return Err(Die::ArgumentError(format!(
+          "Invalid regex flag, see `--help` :: {flag}"
+        )))
+      }
+    };
+  }
+  let swap = if swap { "(?U)" } else { "" };
+  re.pattern(format!("{swap}{pattern}"));
   Ok(re.build()?)
 }
 
-fn p_fzf(fzf: &Option<String>) -> Option<(PathBuf, Vec<String>)> {
+fn p_picker(fzf: Option<&str>) -> Option<Action> {
   match (which("fzf"), stdout().is_terminal(), stderr().is_terminal()) {
-    (Ok(p), true, true) => match fzf.as_deref() {
+    (_, false, _) | (_, _, false) => None,
+    (Ok(p), true, true) if cfg!(target_family = "unix") => match fzf {
+      Some("never") => None,
+      Some(val) => Some(Action::FzfPreview(p, split(val).unwrap_or_default())),
+      None => Some(Action::FzfPreview(p, Vec::new())),
+    },
+    _ => match fzf {
       Some("never") => None,
-      Some(val) => Some((p, split(val).unwrap_or_default())),
-      None => Some((p, Vec::new())),
+      _ => Some(Action::Tui),
     },
-    _ => None,
   }
 }
 
-fn p_pager(pager: &Option<String>) -> Option<SubprocCommand> {
+fn p_command(cmd: &str) -> Result<SubprocCommand, Die> {
+  let mut sh = split(cmd).unwrap_or_default().into_iter();
+  let prog = sh
+    .next()
+    .ok_or_else(|| Die::ArgumentError(format!("Invalid command :: {cmd}")))?;
+  let prog =
+    which(&prog).map_err(|_| Die::ArgumentError(format!("Command not found :: {prog}")))?;
+  Ok(SubprocCommand {
+    prog,
+    args: sh.collect(),
+    env: HashMap::new(),
+  })
+}
+
+fn p_pager(pager: Option<&str>) -> Option<SubprocCommand> {
   let norm = || which("delta").or_else(|_| which("diff-so-fancy")).ok();
 
-  let (prog, arguments) = match pager.as_deref() {
+  let (prog, arguments) = match pager {
     Some("never") => (None, Vec::new()),
     Some(val) => {
       let mut sh = split(val)
@@ -243,39 +710,369 @@ fn p_pager(pager: &Option<String>) -> Option<SubprocCommand> {
   })
 }
 
-pub fn parse_opts(mode: Mode, args: Arguments) -> Result<Options, Die> {
-  let mut flagset = p_auto_flags(args.exact, &args.pattern);
+fn p_inline_flags(pattern: &str) -> (&str, &str) {
+  let flags = "imsuxIMSUX";
+  pattern
+    .strip_prefix("(?")
+    .and_then(|rest| rest.split_once(')'))
+    .filter(|(inline, _)| !inline.is_empty() && inline.chars().all(|c| flags.contains(c)))
+    .unwrap_or(("", pattern))
+}
+
+pub fn p_dictionary(path: &Path) -> Result<Vec<(String, String)>, Die> {
+  let text = read_to_string(path).map_err(|e| Die::IO(path.to_owned(), e.kind()))?;
+  if path.extension().is_some_and(|ext| ext == "json") {
+    let table = from_str::<HashMap<String, String>>(&text)
+      .map_err(|e| Die::ArgumentError(format!("{} :: {e}", path.display())))?;
+    if table.contains_key("") {
+      return Err(Die::ArgumentError(format!(
+        "{} :: empty key",
+        path.display()
+      )));
+    }
+    return Ok(table.into_iter().collect());
+  }
+  text
+    .lines()
+    .enumerate()
+    .filter(|(_, line)| !line.is_empty())
+    .map(|(idx, line)| {
+      line
+        .split_once('\t')
+        .filter(|(key, _)| !key.is_empty())
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .ok_or_else(|| {
+          Die::ArgumentError(format!(
+            "{}:{} :: expected KEY<tab>VALUE",
+            path.display(),
+            idx + 1
+          ))
+        })
+    })
+    .collect()
+}
+
+fn p_words(name: &str) -> Vec<String> {
+  let chars = name.chars().collect::<Vec<_>>();
+  let mut words = Vec::new();
+  let mut word = String::new();
+  let mut prev = None::<char>;
+  for (idx, &c) in chars.iter().enumerate() {
+    if !c.is_alphanumeric() {
+      if !word.is_empty() {
+        words.push(take(&mut word));
+      }
+      prev = None;
+      continue;
+    }
+    let boundary = prev.is_some_and(|p| {
+      let acronym_end = chars.get(idx + 1).is_some_and(|n| n.is_lowercase());
+      c.is_uppercase() && (p.is_lowercase() || p.is_numeric() || (p.is_uppercase() && acronym_end))
+    });
+    if boundary && !word.is_empty() {
+      words.push(take(&mut word));
+    }
+    word.extend(c.to_lowercase());
+    prev = Some(c);
+  }
+  if !word.is_empty() {
+    words.push(word);
+  }
+  words
+}
+
+fn p_styles(words: &[String]) -> Vec<String> {
+  let capital = |word: &String| {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |c| {
+      c.to_uppercase().chain(chars).collect::<String>()
+    })
+  };
+  let upper = words
+    .iter()
+    .map(|word| word.to_uppercase())
+    .collect::<Vec<_>>();
+  let pascal = words.iter().map(capital).collect::<Vec<_>>();
+  let camel = words
+    .iter()
+    .take(1)
+    .cloned()
+    .chain(pascal.iter().skip(1).cloned())
+    .collect::<Vec<_>>();
+  vec![
+    words.join("_"),
+    upper.join("_"),
+    words.join("-"),
+    upper.join("-"),
+    camel.concat(),
+    pascal.concat(),
+  ]
+}
+
+pub fn p_variants(pattern: &str, replace: &str) -> Vec<(String, String)> {
+  let patterns = p_styles(&p_words(pattern));
+  let replaces = p_styles(&p_words(replace));
+  let mut seen = HashSet::new();
+  patterns
+    .into_iter()
+    .zip(replaces)
+    .filter(|(pattern, _)| seen.insert(pattern.clone()))
+    .collect()
+}
+
+/// Smart case only for a single typed pattern, dictionary keys match as written
+///
+/// Pairs share one automaton, so their inline flags must agree
+pub fn p_lexicon(
+  pairs: &[(String, String)],
+  smart: bool,
+  flags: Option<&str>,
+) -> Result<Engine, Die> {
+  let mut patterns = Vec::new();
+  let mut replaces = Vec::new();
+  let mut inlines = None::<&str>;
+  for (pattern, replace) in pairs {
+    let (inline, pattern) = p_inline_flags(pattern);
+    let shared = *inlines.get_or_insert(inline);
+    if shared != inline {
+      return Err(Die::ArgumentError(format!(
+        "exact pairs share flags, inline flags differ :: (?{shared}) (?{inline})"
+      )));
+    }
+    patterns.push(pattern);
+    replaces.push(replace.clone());
+  }
+
+  let mut flagset = if smart {
+    p_auto_flags(true, &patterns.concat())
+  } else {
+    Vec::new()
+  };
   flagset.extend(
-    args
-      .flags
+    flags
       .unwrap_or_default()
-      .split_terminator("")
-      .skip(1)
+      .chars()
+      .chain(inlines.unwrap_or_default().chars())
       .map(String::from),
   );
+  Ok(Engine::AhoCorasick(
+    p_aho_corasick(&patterns, flagset)?,
+    replaces,
+  ))
+}
+
+fn p_engine(
+  pattern: &str,
+  replace: &str,
+  exact: bool,
+  dialect: Dialect,
+  flags: Option<&str>,
+) -> Result<Engine, Die> {
+  if exact {
+    return p_lexicon(&[(pattern.to_owned(), replace.to_owned())], true, flags);
+  }
+  let (inline, pattern) = p_inline_flags(pattern);
+  let mut flagset = p_auto_flags(exact, pattern);
+  flagset.extend(
+    flags
+      .unwrap_or_default()
+      .chars()
+      .chain(inline.chars())
+      .map(String::from),
+  );
+  match dialect {
+    Dialect::Regex => Ok(Engine::Regex(
+      p_regex(pattern, flagset)?,
+      p_template(replace)?,
+    )),
+    Dialect::Fancy => Ok(Engine::Fancy(
+      p_fancy(pattern, flagset)?,
+      p_template(replace)?,
+    )),
+  }
+}
+
+fn p_lines(range: &str) -> Result<(bool, RangeInclusive<usize>), Die> {
+  let (keep, spec) = range
+    .strip_prefix('!')
+    .map_or((true, range), |spec| (false, spec));
+  let (lo, hi) = spec.split_once(':').unwrap_or((spec, spec));
+  let bound = |n: &str, default| {
+    if n.is_empty() {
+      Some(default)
+    } else {
+      n.parse::<usize>().ok()
+    }
+  };
+  match (bound(lo, 1), bound(hi, usize::MAX)) {
+    (Some(lo), Some(hi)) if lo > 0 && lo <= hi => Ok((keep, lo..=hi)),
+    _ => Err(Die::ArgumentError(format!(
+      "Invalid line range, expected FROM:TO :: {range}"
+    ))),
+  }
+}
 
-  let engine = {
-    let replace = args.replace.unwrap_or_default();
-    if args.exact {
-      Engine::AhoCorasick(p_aho_corasick(&args.pattern, flagset)?, replace)
+/// Smart case plus `--flags`, for the regexes that only filter
+fn p_contains(pattern: &str, flags: Option<&str>) -> Result<Regex, Die> {
+  let mut flagset = p_auto_flags(false, pattern);
+  flagset.extend(flags.unwrap_or_default().chars().map(String::from));
+  p_regex(pattern, flagset)
+}
+
+fn p_address(args: &Arguments) -> Result<Address, Die> {
+  let flags = args.flags.as_deref();
+  let markers = |pairs: &[String]| {
+    pairs
+      .chunks_exact(2)
+      .map(|pair| Ok((p_contains(&pair[0], flags)?, p_contains(&pair[1], flags)?)))
+      .collect::<Result<Vec<_>, Die>>()
+  };
+  Ok(Address {
+    lines: args
+      .lines
+      .iter()
+      .map(|range| p_lines(range))
+      .collect::<Result<_, _>>()?,
+    between: markers(&args.between)?,
+    outside: markers(&args.outside)?,
+    if_line: (args.if_line.as_deref())
+      .map(|pattern| p_contains(pattern, flags))
+      .transpose()?,
+    unless_line: (args.unless_line.as_deref())
+      .map(|pattern| p_contains(pattern, flags))
+      .transpose()?,
+  })
+}
+
+fn p_engines(args: &Arguments, exprs: &[(String, String)]) -> Result<Vec<Engine>, Die> {
+  let dictionary = match &args.dictionary {
+    Some(path) => p_dictionary(path)?,
+    None => Vec::new(),
+  };
+  let flags = args.flags.as_deref();
+  if args.exact || args.preserve_case {
+    let mut pairs = if args.preserve_case {
+      exprs
+        .iter()
+        .flat_map(|(pattern, replace)| p_variants(pattern, replace))
+        .collect()
     } else {
-      Engine::Regex(p_regex(&args.pattern, flagset)?, replace)
+      exprs.to_owned()
+    };
+    let smart = !args.preserve_case && dictionary.is_empty() && pairs.len() == 1;
+    pairs.extend(dictionary);
+    if pairs.is_empty() {
+      Ok(Vec::new())
+    } else {
+      Ok(vec![p_lexicon(&pairs, smart, flags)?])
     }
+  } else {
+    let mut engines = match (&args.script, &args.replace_cmd) {
+      (_, Some(cmd)) => {
+        let coprocess = Arc::new(Coprocess::new(p_command(cmd)?, REPLY_TIMEOUT));
+        exprs
+          .iter()
+          .map(
+            |(pattern, _)| match p_engine(pattern, "", false, Dialect::Regex, flags)? {
+              Engine::Regex(re, _) => Ok(Engine::Command(re, coprocess.clone())),
+              engine => Ok(engine),
+            },
+          )
+          .collect::<Result<Vec<_>, Die>>()?
+      }
+      (Some(path), None) => {
+        let script = Arc::new(p_script(path)?);
+        exprs
+          .iter()
+          .map(
+            |(pattern, _)| match p_engine(pattern, "", false, Dialect::Regex, flags)? {
+              Engine::Regex(re, _) => Ok(Engine::Script(re, script.clone())),
+              engine => Ok(engine),
+            },
+          )
+          .collect::<Result<Vec<_>, Die>>()?
+      }
+      (None, None) => exprs
+        .iter()
+        .map(|(pattern, replace)| p_engine(pattern, replace, false, args.engine, flags))
+        .collect::<Result<Vec<_>, _>>()?,
+    };
+    if !dictionary.is_empty() {
+      engines.push(p_lexicon(&dictionary, false, flags)?);
+    }
+    Ok(engines)
+  }
+}
+
+pub fn parse_opts(args: Arguments) -> Result<Options, Die> {
+  let mut exprs = match &args.pattern {
+    Some(pattern) => vec![(pattern.clone(), args.replace.clone().unwrap_or_default())],
+    None => Vec::new(),
   };
+  for pair in args.exprs.chunks_exact(2) {
+    exprs.push((pair[0].clone(), pair[1].clone()));
+  }
+  let engines = p_engines(&args, &exprs)?;
+  let group = args.group.as_deref().map_or(Group::Index(0), p_group);
+  if let Some(engine) = engines.iter().find(|engine| !engine.has_group(&group)) {
+    let pattern = match engine {
+      Engine::AhoCorasick(..) => "",
+      Engine::Regex(re, _) | Engine::Script(re, _) | Engine::Command(re, _) => re.as_str(),
+      Engine::Fancy(re, _) => re.as_str(),
+    };
+    return Err(Die::ArgumentError(format!(
+      "No capture group {} in {pattern}",
+      args.group.unwrap_or_default()
+    )));
+  }
+  if args.occurrence == Some(0) {
+    return Err(Die::ArgumentError("--occurrence counts from 1".to_owned()));
+  }
+  if args.per_match && engines.len() > 1 {
+    return Err(Die::ArgumentError(
+      "--per-match takes a single expression".to_owned(),
+    ));
+  }
 
-  let action = match (args.commit, mode, p_fzf(&args.fzf)) {
-    (true, _, _) | (_, Mode::Patch(_), _) => Action::Commit,
-    (_, Mode::Initial, Some((bin, args))) => Action::FzfPreview(bin, args),
-    _ => Action::Preview,
+  let action = if args.commit {
+    Action::Commit
+  } else if args.interactive {
+    Action::Interactive
+  } else {
+    p_picker(args.fzf.as_deref()).unwrap_or(Action::Preview)
   };
+  if args.live.is_some() && !matches!(action, Action::FzfPreview(_, _)) {
+    return Err(Die::ArgumentError("--live requires fzf".to_owned()));
+  }
 
-  let printer = p_pager(&args.pager).map_or(Printer::Stdout, Printer::Pager);
+  let printer = p_pager(args.pager.as_deref()).map_or(Printer::Stdout, Printer::Pager);
+  let ledger = Ledger::new(args.max_total, &engines);
 
   Ok(Options {
     cwd: current_dir().ok(),
+    exprs,
     action,
-    engine,
+    engines,
     printer,
     unified: args.unified.unwrap_or(3),
+    per_match: args.per_match,
+    exact: args.exact,
+    dialect: args.engine,
+    preserve_case: args.preserve_case,
+    group,
+    address: p_address(&args)?,
+    if_contains: (args.if_contains.as_deref())
+      .map(|pattern| p_contains(pattern, args.flags.as_deref()))
+      .transpose()?,
+    unless_contains: (args.unless_contains.as_deref())
+      .map(|pattern| p_contains(pattern, args.flags.as_deref()))
+      .transpose()?,
+    occurrence: args.occurrence,
+    max_per_file: args.max_per_file,
+    max_total: args.max_total,
+    ledger,
+    flags: args.flags,
+    live: args.live,
+    journal: !args.no_journal,
   })
 }
@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod spec {
+  use super::super::{
+    argparse::{p_dictionary, p_lexicon, p_positionals, p_variants, parse_opts, Arguments, Engine},
+    fixture::{pairs, scratch},
+  };
+  use {
+    clap::Parser,
+    std::{fs::remove_dir_all, path::PathBuf},
+  };
+
+  fn hits(engine: &Engine, text: &str) -> Vec<String> {
+    let Engine::AhoCorasick(ac, _) = engine else {
+      panic!("expected aho-corasick")
+    };
+    ac.find_iter(text)
+      .map(|m| text[m.range()].to_owned())
+      .collect()
+  }
+
+  #[test]
+  fn preserve_case() {
+    let variants = p_variants("user_id", "account_id");
+    let expected = [
+      ("user_id", "account_id"),
+      ("USER_ID", "ACCOUNT_ID"),
+      ("user-id", "account-id"),
+      ("USER-ID", "ACCOUNT-ID"),
+      ("userId", "accountId"),
+      ("UserId", "AccountId"),
+    ];
+    assert_eq!(
+      variants,
+      expected.map(|(p, r)| (p.to_owned(), r.to_owned()))
+    );
+
+    let variants = p_variants("HTTPServer", "proxy");
+    assert_eq!(variants[0], ("http_server".to_owned(), "proxy".to_owned()));
+    assert_eq!(variants[5], ("HttpServer".to_owned(), "Proxy".to_owned()));
+
+    let variants = p_variants("user", "account");
+    let patterns = variants.iter().map(|(p, _)| p.as_str()).collect::<Vec<_>>();
+    assert_eq!(patterns, ["user", "USER", "User"]);
+  }
+
+  #[test]
+  fn dictionary() {
+    let dir = scratch(&[
+      ("a.tsv", "foo\tbar\n\nbaz\tqux\tquux\n"),
+      ("b.json", r#"{"foo": "bar"}"#),
+      ("c.tsv", "foo\tbar\nbaz\n"),
+      ("d.json", r#"{"foo": "bar", "": "baz"}"#),
+    ]);
+    let mut table = p_dictionary(&dir.join("a.tsv")).unwrap();
+    table.sort();
+    assert_eq!(table, pairs(&[("baz", "qux\tquux"), ("foo", "bar")]));
+
+    let table = p_dictionary(&dir.join("b.json")).unwrap();
+    assert_eq!(table, pairs(&[("foo", "bar")]));
+
+    let path = dir.join("c.tsv");
+    let err = p_dictionary(&path).unwrap_err().to_string();
+    assert!(err.contains(&format!("{}:2", path.display())), "{err}");
+
+    let err = p_dictionary(&dir.join("d.json")).unwrap_err().to_string();
+    assert!(err.contains("empty key"), "{err}");
+    remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn lexicon_case() {
+    let table = pairs(&[("foo", "x"), ("Bar", "y")]);
+    let exact = p_lexicon(&table, false, None).unwrap();
+    assert_eq!(hits(&exact, "foo FOO Bar bar"), ["foo", "Bar"]);
+
+    let folded = p_lexicon(&table, false, Some("i")).unwrap();
+    assert_eq!(
+      hits(&folded, "foo FOO Bar bar"),
+      ["foo", "FOO", "Bar", "bar"]
+    );
+
+    let smart = p_lexicon(&pairs(&[("foo", "x")]), true, None).unwrap();
+    assert_eq!(hits(&smart, "foo FOO"), ["foo", "FOO"]);
+
+    let inline = p_lexicon(&pairs(&[("(?i)foo", "x"), ("(?i)bar", "y")]), false, None).unwrap();
+    assert_eq!(hits(&inline, "Foo BAR"), ["Foo", "BAR"]);
+
+    let mixed = pairs(&[("foo", "x"), ("(?i)bar", "y")]);
+    assert!(p_lexicon(&mixed, false, None).is_err());
+  }
+
+  #[test]
+  fn positionals() {
+    let dir = scratch(&[("a.txt", ""), ("b.txt", "")]);
+    let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    let mut args = Arguments::parse_from(["sad", a, b, "--expr", "foo", "bar"]);
+    p_positionals(&mut args).unwrap();
+    assert_eq!((args.pattern, args.replace), (None, None));
+    assert_eq!(args.paths, [a, b].map(PathBuf::from));
+
+    let mut args = Arguments::parse_from(["sad", "foo", "bar", "--expr", "baz", "qux", a]);
+    let err = p_positionals(&mut args).unwrap_err();
+    assert!(err.ends_with(":: foo"), "{err}");
+
+    let mut args = Arguments::parse_from(["sad", a, "bar", "--dictionary", b]);
+    let err = p_positionals(&mut args).unwrap_err();
+    assert!(err.ends_with(":: bar"), "{err}");
+    remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn missing_group() {
+    let opts = |group: &str| {
+      parse_opts(Arguments::parse_from([
+        "sad",
+        "(?<key>foo)",
+        "bar",
+        "--group",
+        group,
+      ]))
+    };
+    assert!(opts("1").is_ok());
+    assert!(opts("key").is_ok());
+    let err = opts("2").unwrap_err().to_string();
+    assert!(err.contains("No capture group 2"), "{err}");
+    let err = opts("nope").unwrap_err().to_string();
+    assert!(err.contains("No capture group nope"), "{err}");
+  }
+}
@@ -1,35 +1,446 @@
 use {
   super::{
-    argparse::{Action, Engine, Options},
-    fs_pipe::{slurp, spit},
+    argparse::{Action, Address, Engine, Options},
+    fs_pipe::{commit, slurp, stage, Staged},
     input::RowIn,
+    journal::record,
+    template::{Group, Scope},
     types::Die,
-    udiff::{apply_patches, patches, pure_diffs, udiff},
+    udiff::{
+      apply_edits, apply_patches, digest, match_units, patches, pure_diffs, relocate, udiff,
+      DiffRange, Edit,
+    },
   },
-  ansi_term::Colour,
-  std::{borrow::ToOwned, ffi::OsString, path::PathBuf},
+  ansi_term::{Colour, Style},
+  futures::future::try_join_all,
+  regex::{Captures, Regex},
+  std::{
+    borrow::ToOwned,
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    iter::once,
+    ops::Range,
+    path::{Path, PathBuf},
+  },
+  tokio::task::spawn_blocking,
 };
 
+fn p_target(captures: &Captures, target: &Group) -> Option<Range<usize>> {
+  match target {
+    Group::Index(idx) => captures.get(*idx),
+    Group::Name(name) => captures.name(name),
+  }
+  .map(|m| m.range())
+}
+
 impl Engine {
-  fn replace(&self, before: &str) -> String {
+  pub fn unmapped(&self) -> Vec<(PathBuf, Vec<String>)> {
     match self {
-      Self::AhoCorasick(ac, replace) => ac.replace_all(before, &[replace.as_str()]),
-      Self::Regex(re, replace) => re.replace_all(before, replace.as_str()).into(),
+      Self::Regex(_, template) | Self::Fancy(_, template) => template.unmapped(),
+      _ => Vec::new(),
+    }
+  }
+
+  /// Matches in `limit`'s text, each expanded only once it is in scope
+  pub fn edits(
+    &self,
+    name: &Path,
+    target: &Group,
+    base: usize,
+    mut limit: Limit,
+  ) -> Result<Vec<Edit>, Die> {
+    let before = limit.before;
+    let mut offset = 0;
+    let mut line = 1;
+    let mut scope = |count, start: usize| {
+      line += before[offset..start].matches('\n').count();
+      offset = start;
+      Scope {
+        name,
+        count,
+        total: base + count,
+        line,
+      }
+    };
+    match self {
+      Self::AhoCorasick(ac, replaces) => {
+        for m in ac.find_iter(before) {
+          if limit.full() {
+            break;
+          }
+          if limit.admit(&m.range()) {
+            limit.keep(m.range(), replaces[m.pattern().as_usize()].clone());
+          }
+        }
+      }
+      Self::Regex(re, template) => {
+        for captures in re.captures_iter(before) {
+          if limit.full() {
+            break;
+          }
+          let Some(span) = p_target(&captures, target).filter(|span| limit.admit(span)) else {
+            continue;
+          };
+          let get = |group: &Group| match group {
+            Group::Index(idx) => captures.get(*idx),
+            Group::Name(name) => captures.name(name),
+          };
+          let replacement = template.expand(&scope(limit.count(), span.start), |group| {
+            get(group).map(|m| m.as_str())
+          });
+          if let Some(replacement) = replacement {
+            limit.keep(span, replacement);
+          }
+        }
+      }
+      Self::Fancy(re, template) => {
+        for captures in re.captures_iter(before) {
+          if limit.full() {
+            break;
+          }
+          let captures = captures?;
+          let get = |group: &Group| match group {
+            Group::Index(idx) => captures.get(*idx),
+            Group::Name(name) => captures.name(name),
+          };
+          let Some(span) = get(target)
+            .map(|m| m.range())
+            .filter(|span| limit.admit(span))
+          else {
+            continue;
+          };
+          let replacement = template.expand(&scope(limit.count(), span.start), |group| {
+            get(group).map(|m| m.as_str())
+          });
+          if let Some(replacement) = replacement {
+            limit.keep(span, replacement);
+          }
+        }
+      }
+      Self::Script(re, script) => {
+        for captures in re.captures_iter(before) {
+          if limit.full() {
+            break;
+          }
+          let Some(span) = p_target(&captures, target).filter(|span| limit.admit(span)) else {
+            continue;
+          };
+          let replacement = script.replace(re, &captures, &scope(limit.count(), span.start))?;
+          if let Some(replacement) = replacement {
+            limit.keep(span, replacement);
+          }
+        }
+      }
+      Self::Command(re, coprocess) => {
+        for captures in re.captures_iter(before) {
+          if limit.full() {
+            break;
+          }
+          let Some(span) = p_target(&captures, target).filter(|span| limit.admit(span)) else {
+            continue;
+          };
+          if let Some(replacement) = coprocess.call(&before[span.clone()])? {
+            limit.keep(span, replacement);
+          }
+        }
+      }
+    }
+    Ok(limit.edits)
+  }
+}
+
+impl Address {
+  /// Which lines of `text` may be edited
+  pub fn mask(&self, text: &str) -> Vec<bool> {
+    let lines = text
+      .split_inclusive('\n')
+      .map(|line| line.strip_suffix('\n').unwrap_or(line))
+      .collect::<Vec<_>>();
+    let kept = (self.lines.iter())
+      .filter(|(keep, _)| *keep)
+      .collect::<Vec<_>>();
+    let mut mask = lines
+      .iter()
+      .enumerate()
+      .map(|(idx, line)| {
+        let number = idx + 1;
+        (kept.is_empty() || kept.iter().any(|(_, range)| range.contains(&number)))
+          && !(self.lines.iter()).any(|(keep, range)| !keep && range.contains(&number))
+          && self.if_line.as_ref().is_none_or(|re| re.is_match(line))
+          && !self
+            .unless_line
+            .as_ref()
+            .is_some_and(|re| re.is_match(line))
+      })
+      .collect::<Vec<_>>();
+
+    let regions = |(start, end): &(Regex, Regex)| {
+      let mut inside = false;
+      lines
+        .iter()
+        .map(|line| {
+          let marker = if inside {
+            end.is_match(line)
+          } else {
+            start.is_match(line)
+          };
+          if marker {
+            inside = !inside;
+          }
+          (inside, marker)
+        })
+        .collect::<Vec<_>>()
+    };
+    if !self.between.is_empty() {
+      let between = self.between.iter().map(regions).collect::<Vec<_>>();
+      for (idx, allowed) in mask.iter_mut().enumerate() {
+        *allowed &= between.iter().any(|region| region[idx].0 && !region[idx].1);
+      }
+    }
+    for region in self.outside.iter().map(regions) {
+      for (allowed, (inside, marker)) in mask.iter_mut().zip(region) {
+        *allowed &= !(inside || marker);
+      }
     }
+    mask
+  }
+}
+
+/// `--lines` & co pick the matches looked at, `--occurrence` `--max-per-file` `--max-total` count those that change something
+pub struct Limit<'a> {
+  before: &'a str,
+  mask: Option<(Vec<bool>, Vec<usize>)>,
+  occurrence: Option<usize>,
+  cap: usize,
+  offset: usize,
+  seen: usize,
+  edits: Vec<Edit>,
+}
+
+impl<'a> Limit<'a> {
+  pub fn new(
+    before: &'a str,
+    mask: Option<Vec<bool>>,
+    occurrence: Option<usize>,
+    cap: usize,
+  ) -> Self {
+    let mask = mask.map(|mask| {
+      let starts = once(0)
+        .chain(before.match_indices('\n').map(|(idx, _)| idx + 1))
+        .collect();
+      (mask, starts)
+    });
+    Self {
+      before,
+      mask,
+      occurrence,
+      cap,
+      offset: 0,
+      seen: 0,
+      edits: Vec::new(),
+    }
+  }
+
+  const fn full(&self) -> bool {
+    self.edits.len() >= self.cap
+  }
+
+  const fn count(&self) -> usize {
+    self.edits.len()
+  }
+
+  /// Every line the span touches is in scope
+  fn admit(&self, span: &Range<usize>) -> bool {
+    self.mask.as_ref().is_none_or(|(mask, starts)| {
+      let line_of = |offset: usize| starts.partition_point(|&start| start <= offset) - 1;
+      let last = span.end.saturating_sub(1).max(span.start);
+      (line_of(span.start)..=line_of(last)).all(|line| mask.get(line).copied().unwrap_or(false))
+    })
+  }
+
+  fn keep(&mut self, span: Range<usize>, replacement: String) {
+    if self.before[span.clone()] == replacement {
+      return;
+    }
+    if let Some(occurrence) = self.occurrence {
+      if self.before[self.offset..span.start].contains('\n') {
+        self.seen = 0;
+      }
+      self.offset = span.start;
+      self.seen += 1;
+      if self.seen != occurrence {
+        return;
+      }
+    }
+    self.edits.push(Edit { span, replacement });
+  }
+}
+
+/// `--replace-cmd` blocks on its child, keep it off the async workers
+async fn scan(
+  engine: &Engine,
+  opts: &Options,
+  name: &OsStr,
+  (base, room): (usize, usize),
+  text: &str,
+) -> Result<Vec<Edit>, Die> {
+  let mask = (!opts.address.is_empty()).then(|| opts.address.mask(text));
+  let (occurrence, cap) = (
+    opts.occurrence,
+    opts.max_per_file.unwrap_or(usize::MAX).min(room),
+  );
+  if !matches!(engine, Engine::Command(..)) {
+    let limit = Limit::new(text, mask, occurrence, cap);
+    return engine.edits(Path::new(name), &opts.group, base, limit);
   }
+  let (engine, group) = (engine.clone(), opts.group.clone());
+  let (name, text) = (PathBuf::from(name), text.to_owned());
+  spawn_blocking(move || {
+    let limit = Limit::new(&text, mask, occurrence, cap);
+    engine.edits(&name, &group, base, limit)
+  })
+  .await
+  .expect("replace-cmd task")
 }
 
 impl RowIn {
-  const fn path(&self) -> &PathBuf {
+  pub const fn path(&self) -> &PathBuf {
     match self {
-      Self::Entire(path) | Self::Piecewise(path, _) => path,
+      Self::Entire(path) | Self::Piecewise(path, _, _) => path,
     }
   }
 }
 
-pub async fn displace(opts: &Options, input: RowIn) -> Result<OsString, Die> {
+pub struct Diffed {
+  pub path: PathBuf,
+  pub name: OsString,
+  pub before: Vec<String>,
+  pub after: Vec<String>,
+  pub edits: Option<Vec<Edit>>,
+}
+
+impl Diffed {
+  pub fn units(&self, unified: usize) -> Vec<DiffRange> {
+    self.edits.as_ref().map_or_else(
+      || pure_diffs(unified, &self.before, &self.after),
+      |edits| match_units(&self.before, edits),
+    )
+  }
+
+  fn picked(&self, ranges: &HashSet<DiffRange>) -> Option<Vec<String>> {
+    self.edits.as_ref().map(|edits| {
+      let units = match_units(&self.before, edits);
+      let picked = edits
+        .iter()
+        .zip(units)
+        .filter(|(_, unit)| ranges.contains(unit))
+        .map(|(edit, _)| edit);
+      apply_edits(&self.before.concat(), picked)
+        .split_inclusive('\n')
+        .map(ToOwned::to_owned)
+        .collect()
+    })
+  }
+
+  fn summary(&self, range: &DiffRange) -> String {
+    let picked = self.picked(&HashSet::from([range.clone()]));
+    let after = picked.as_deref().unwrap_or(&self.after);
+    let before = self.before.iter().skip(range.before.0).take(range.before.1);
+    let after = after.iter().skip(range.after.0).take(range.after.1);
+    let skip = before
+      .clone()
+      .zip(after.clone())
+      .take_while(|(b, a)| b == a)
+      .count();
+    let clean = |line: Option<&String>| {
+      line.map(|line| line.trim_end_matches(['\r', '\n']).replace('\0', ""))
+    };
+
+    match (
+      clean(before.clone().nth(skip)),
+      clean(after.clone().nth(skip)),
+    ) {
+      (Some(removed), Some(added)) => {
+        let prefix = removed
+          .chars()
+          .zip(added.chars())
+          .take_while(|(r, a)| r == a)
+          .map(|(r, _)| r.len_utf8())
+          .sum::<usize>();
+        let suffix = removed[prefix..]
+          .chars()
+          .rev()
+          .zip(added[prefix..].chars().rev())
+          .take_while(|(r, a)| r == a)
+          .map(|(r, _)| r.len_utf8())
+          .sum::<usize>();
+        let mark = |line: &str, colour: Colour, sign: char| {
+          let (head, rest) = line.split_at(prefix);
+          let (mid, tail) = rest.split_at(rest.len() - suffix);
+          format!(
+            "{}{head}{}{tail}",
+            colour.paint(sign.to_string()),
+            colour.bold().underline().paint(mid)
+          )
+        };
+        format!(
+          "\n{}\n{}",
+          mark(&removed, Colour::Red, '-'),
+          mark(&added, Colour::Green, '+')
+        )
+      }
+      (Some(removed), None) => format!("\n{}", Colour::Red.paint(format!("-{removed}"))),
+      (None, Some(added)) => format!("\n{}", Colour::Green.paint(format!("+{added}"))),
+      (None, None) => String::new(),
+    }
+  }
+
+  pub fn render(&self, unified: usize, ranges: Option<&HashSet<DiffRange>>) -> OsString {
+    ranges.and_then(|ranges| self.picked(ranges)).map_or_else(
+      || udiff(ranges, unified, &self.name, &self.before, &self.after),
+      |after| udiff(None, unified, &self.name, &self.before, &after),
+    )
+  }
+
+  fn patch(
+    &self,
+    unified: usize,
+    ranges: &HashSet<DiffRange>,
+    lines: &HashMap<String, Vec<String>>,
+  ) -> Vec<String> {
+    if let Some(after) = self.picked(ranges) {
+      return after;
+    }
+    let mut patches = patches(unified, &self.before, &self.after);
+    for patch in &mut patches {
+      if let Some(lines) = lines.get(&digest(&patch.range, &self.before)) {
+        patch.new_lines = lines.iter().map(String::as_str).collect();
+      }
+    }
+    apply_patches(patches, ranges, &self.before)
+      .into_iter()
+      .map(ToOwned::to_owned)
+      .collect()
+  }
+}
+
+pub enum Displaced {
+  Print(OsString),
+  Stage(OsString, Staged),
+  Listed(OsString, Diffed),
+}
+
+impl Displaced {
+  pub fn into_print(self) -> OsString {
+    match self {
+      Self::Print(print) | Self::Stage(print, _) | Self::Listed(print, _) => print,
+    }
+  }
+}
+
+pub async fn displace(opts: &Options, input: RowIn) -> Result<Displaced, Die> {
   let path = input.path().clone();
-  let mut name = opts
+  let name = opts
     .cwd
     .as_ref()
     .and_then(|cwd| path.strip_prefix(cwd).ok())
@@ -39,47 +450,106 @@ pub async fn displace(opts: &Options, input: RowIn) -> Result<OsString, Die> {
 
   let slurped = slurp(&path).await?;
   let before = slurped.content;
-  let replaced = {
-    let b = before.clone().into_iter().collect::<String>();
-    opts.engine.replace(&b)
-  };
+  let pre_image = before.concat();
+  let wanted = (opts.if_contains.as_ref()).is_none_or(|re| re.is_match(&pre_image))
+    && !(opts.unless_contains.as_ref()).is_some_and(|re| re.is_match(&pre_image));
+  let engines = if wanted { opts.engines.as_slice() } else { &[] };
+  let mut edits = Vec::new();
+  let mut replaced = pre_image.clone();
+  for (nth, engine) in engines.iter().enumerate() {
+    let opened = (opts.ledger.as_ref()).map_or((0, usize::MAX), |l| l.open(&path, nth));
+    edits = scan(engine, opts, &name, opened, &replaced).await?;
+    if let Some(ledger) = &opts.ledger {
+      ledger.close(&path, nth, edits.len());
+    }
+    replaced = apply_edits(&replaced, &edits);
+  }
   let after = replaced
     .split_inclusive('\n')
     .map(ToOwned::to_owned)
     .collect::<Vec<_>>();
+  let diffed = Diffed {
+    path,
+    name,
+    before,
+    after,
+    edits: opts.per_match.then_some(edits),
+  };
 
-  if *before == after {
-    Ok(OsString::new())
-  } else {
-    let print = match (&opts.action, input) {
-      (Action::Preview, RowIn::Entire(_)) => udiff(None, opts.unified, &name, &before, &after),
-      (Action::Preview, RowIn::Piecewise(_, ranges)) => {
-        udiff(Some(&ranges), opts.unified, &name, &before, &after)
-      }
-      (Action::Commit, RowIn::Entire(_)) => {
-        spit(&path, &slurped.meta, after).await?;
-        name.push("\n");
-        name
-      }
-      (Action::Commit, RowIn::Piecewise(_, ranges)) => {
-        let patches = patches(opts.unified, &before, &after);
-        let after = apply_patches(patches, &ranges, &before);
-        spit(&path, &slurped.meta, after).await?;
-        name.push("\n");
-        name
+  let ranges = match &input {
+    RowIn::Entire(_) => None,
+    RowIn::Piecewise(_, picks, _) => Some(
+      relocate(picks, diffed.units(opts.unified), &diffed.before)
+        .map_err(|range| Die::Stale(diffed.path.clone(), format!("{range}")))?,
+    ),
+  };
+
+  if diffed.before == diffed.after {
+    return Ok(Displaced::Print(OsString::new()));
+  }
+  let displaced = match (&opts.action, ranges) {
+    (Action::Preview, ranges) => Displaced::Print(diffed.render(opts.unified, ranges.as_ref())),
+    (Action::Commit, ranges) => {
+      let after = match (&input, ranges) {
+        (RowIn::Piecewise(_, _, lines), Some(ranges)) => diffed.patch(opts.unified, &ranges, lines),
+        _ => diffed.after,
+      };
+      let staged = stage(
+        &diffed.path,
+        slurped.meta.permissions(),
+        pre_image.into_bytes(),
+        after,
+      )
+      .await?;
+      let mut name = diffed.name;
+      name.push("\n");
+      Displaced::Stage(name, staged)
+    }
+    (Action::FzfPreview(_, _), _) => {
+      let mut fzf_lines = OsString::new();
+      for range in diffed.units(opts.unified) {
+        let repr = Colour::Red.paint(format!("{range}"));
+        let digest = Style::new().dimmed().paint(digest(&range, &diffed.before));
+        let summary = diffed.summary(&range);
+        fzf_lines.push(&diffed.name);
+        let line = format!("\n\n\n\n{repr} {digest}{summary}\0");
+        fzf_lines.push(&line);
       }
-      (Action::FzfPreview(_, _), _) => {
-        let ranges = pure_diffs(opts.unified, &before, &after);
-        let mut fzf_lines = OsString::new();
-        for range in ranges {
-          let repr = Colour::Red.paint(format!("{range}"));
-          fzf_lines.push(&name);
-          let line = format!("\n\n\n\n{repr}\0");
-          fzf_lines.push(&line);
-        }
-        fzf_lines
+      Displaced::Listed(fzf_lines, diffed)
+    }
+    (Action::Tui | Action::Interactive, _) => Displaced::Listed(OsString::new(), diffed),
+  };
+  Ok(displaced)
+}
+
+pub async fn transact(opts: &Options, displaced: Vec<Displaced>) -> Result<Vec<OsString>, Die> {
+  let mut names = Vec::new();
+  let mut staged = Vec::new();
+  for d in displaced {
+    match d {
+      Displaced::Stage(name, s) => {
+        names.push(name);
+        staged.push(s);
       }
-    };
-    Ok(print)
+      d => names.push(d.into_print()),
+    }
   }
+
+  let journal = record(opts, &staged).await;
+  if let Err(e) = commit(staged).await {
+    if let Some(journal) = journal {
+      journal.discard().await;
+    }
+    return Err(e);
+  }
+  Ok(names)
+}
+
+pub async fn apply(opts: &Options, rows: Vec<RowIn>) -> Result<Vec<OsString>, Die> {
+  let opts = Options {
+    action: Action::Commit,
+    ..opts.clone()
+  };
+  let displaced = try_join_all(rows.into_iter().map(|row| displace(&opts, row))).await?;
+  transact(&opts, displaced).await
 }
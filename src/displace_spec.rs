@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod spec {
+  use super::super::{
+    argparse::{Address, Engine},
+    displace::Limit,
+    fixture::pairs,
+    template::{p_template, Group},
+  };
+  use regex::Regex;
+  use std::path::Path;
+
+  fn edits(pattern: &str, replace: &str, text: &str, limit: Limit) -> Vec<(String, String)> {
+    let engine = Engine::Regex(Regex::new(pattern).unwrap(), p_template(replace).unwrap());
+    engine
+      .edits(Path::new("a.txt"), &Group::Index(0), 0, limit)
+      .unwrap()
+      .into_iter()
+      .map(|edit| (text[edit.span].to_owned(), edit.replacement))
+      .collect()
+  }
+
+  #[test]
+  fn limits() {
+    let text = "foo fooo\n";
+    let limit = Limit::new(text, None, None, 1);
+    assert_eq!(edits("fo+", "foo", text, limit), pairs(&[("fooo", "foo")]));
+
+    let text = "foo foo\nfoo foo\nfoo\n";
+    let limit = Limit::new(text, None, Some(2), usize::MAX);
+    assert_eq!(
+      edits("foo", "n${#}", text, limit),
+      pairs(&[("foo", "n1"), ("foo", "n2")])
+    );
+
+    let limit = Limit::new(text, None, None, 3);
+    assert_eq!(
+      edits("foo", "n${#}", text, limit),
+      pairs(&[("foo", "n1"), ("foo", "n2"), ("foo", "n3")])
+    );
+  }
+
+  fn address() -> Address {
+    Address {
+      lines: Vec::new(),
+      between: Vec::new(),
+      outside: Vec::new(),
+      if_line: None,
+      unless_line: None,
+    }
+  }
+
+  #[test]
+  fn mask() {
+    let text = "a\nBEGIN\nb\nEND\nc\n";
+    let re = |pattern| Regex::new(pattern).unwrap();
+
+    let lines = Address {
+      lines: vec![(true, 2..=4), (false, 3..=3)],
+      ..address()
+    };
+    assert_eq!(lines.mask(text), [false, true, false, true, false]);
+
+    let between = Address {
+      between: vec![(re("BEGIN"), re("END"))],
+      ..address()
+    };
+    assert_eq!(between.mask(text), [false, false, true, false, false]);
+
+    let outside = Address {
+      outside: vec![(re("BEGIN"), re("END"))],
+      unless_line: Some(re("c")),
+      ..address()
+    };
+    assert_eq!(outside.mask(text), [true, false, false, false, false]);
+  }
+
+  #[test]
+  fn scoped() {
+    let text = "foo\nfoo\nfoo foo\n";
+    let mask = Address {
+      lines: vec![(true, 2..=3)],
+      ..address()
+    }
+    .mask(text);
+    let limit = Limit::new(text, Some(mask.clone()), None, usize::MAX);
+    assert_eq!(
+      edits("foo", "n${#}", text, limit),
+      pairs(&[("foo", "n1"), ("foo", "n2"), ("foo", "n3")])
+    );
+
+    let limit = Limit::new(text, Some(mask), Some(2), usize::MAX);
+    assert_eq!(edits("foo", "n${#}", text, limit), pairs(&[("foo", "n1")]));
+
+    let limit = Limit::new(text, None, None, usize::MAX);
+    assert_eq!(edits("o\nf", "", text, limit).len(), 2);
+    let mask = vec![true, false, true];
+    let limit = Limit::new(text, Some(mask), None, usize::MAX);
+    assert!(edits("o\nf", "", text, limit).is_empty());
+  }
+
+  #[test]
+  fn group() {
+    let text = "x=1 y=2 z\n";
+    let re = Regex::new(r"(?<key>\w)(?:=(\d))?").unwrap();
+    let engine = Engine::Regex(re, p_template("[$0]").unwrap());
+    let grouped = |target: &Group| {
+      let limit = Limit::new(text, None, None, usize::MAX);
+      (engine.edits(Path::new("a.txt"), target, 0, limit).unwrap())
+        .into_iter()
+        .map(|edit| (text[edit.span].to_owned(), edit.replacement))
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      grouped(&Group::Index(2)),
+      pairs(&[("1", "[x=1]"), ("2", "[y=2]")])
+    );
+    assert_eq!(
+      grouped(&Group::Name("key".to_owned())),
+      pairs(&[("x", "[x=1]"), ("y", "[y=2]"), ("z", "[z]")])
+    );
+  }
+}
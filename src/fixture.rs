@@ -0,0 +1,54 @@
+use {
+  super::fs_pipe::{stage, Staged},
+  std::{
+    env::temp_dir,
+    fs::{create_dir_all, metadata, read_dir, read_to_string, write},
+    future::Future,
+    path::{Path, PathBuf},
+  },
+  tokio::runtime::Builder,
+  uuid::Uuid,
+};
+
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+  Builder::new_current_thread().build().unwrap().block_on(fut)
+}
+
+/// Fresh temp dir holding `files`, ie. `("sub/a.txt", "a\n")`
+pub fn scratch(files: &[(&str, &str)]) -> PathBuf {
+  let dir = temp_dir().join(format!("sad-spec-{}", Uuid::new_v4().as_simple()));
+  for (name, text) in files {
+    let path = dir.join(name);
+    create_dir_all(path.parent().unwrap()).unwrap();
+    write(path, text).unwrap();
+  }
+  create_dir_all(&dir).unwrap();
+  dir
+}
+
+/// Sorted names directly under `dir`
+pub fn listing(dir: &Path) -> Vec<String> {
+  let mut names = read_dir(dir)
+    .unwrap()
+    .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+    .collect::<Vec<_>>();
+  names.sort();
+  names
+}
+
+pub fn pairs(table: &[(&str, &str)]) -> Vec<(String, String)> {
+  table
+    .iter()
+    .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+    .collect()
+}
+
+/// `name` under `dir` staged to be rewritten as `text`
+pub async fn staged(dir: &Path, name: &str, text: &str) -> Staged {
+  let path = dir.join(name);
+  let permissions = metadata(&path).unwrap().permissions();
+  let pre_image = read_to_string(&path).unwrap().into_bytes();
+  stage(&path, permissions, pre_image, vec![text.as_bytes()])
+    .await
+    .unwrap()
+}
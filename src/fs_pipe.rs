@@ -1,8 +1,13 @@
 use {
   super::types::Die,
-  std::{borrow::ToOwned, fs::Metadata, path::Path},
+  blake3::{Hash, Hasher},
+  std::{
+    borrow::ToOwned,
+    fs::{remove_file, Metadata, Permissions},
+    path::{Path, PathBuf},
+  },
   tokio::{
-    fs::{rename, File, OpenOptions},
+    fs::{self, copy, hard_link, rename, File, OpenOptions},
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
   },
   uuid::Uuid,
@@ -44,16 +49,28 @@ pub async fn slurp(path: &Path) -> Result<Slurpee, Die> {
         }
       }
     }
-  };
+  }
 
   Ok(slurm)
 }
 
-pub async fn spit(
-  canonical: &Path,
-  meta: &Metadata,
-  text: Vec<impl AsRef<[u8]> + Send>,
-) -> Result<(), Die> {
+pub struct Staged {
+  pub canonical: PathBuf,
+  pub permissions: Permissions,
+  pub pre_image: Vec<u8>,
+  pub digest: Hash,
+  tmp: Option<PathBuf>,
+}
+
+impl Drop for Staged {
+  fn drop(&mut self) {
+    if let Some(tmp) = self.tmp.take() {
+      let _ = remove_file(tmp);
+    }
+  }
+}
+
+fn sibling(canonical: &Path, suffix: &str) -> PathBuf {
   let uuid = Uuid::new_v4().as_simple().to_string();
   let mut file_name = canonical
     .file_name()
@@ -61,7 +78,24 @@ pub async fn spit(
     .unwrap_or_default();
   file_name.push("___");
   file_name.push(uuid);
-  let tmp = canonical.with_file_name(file_name);
+  file_name.push(suffix);
+  canonical.with_file_name(file_name)
+}
+
+pub async fn stage(
+  canonical: &Path,
+  permissions: Permissions,
+  pre_image: Vec<u8>,
+  text: Vec<impl AsRef<[u8]> + Send>,
+) -> Result<Staged, Die> {
+  let tmp = sibling(canonical, "");
+  let digest = {
+    let mut hasher = Hasher::new();
+    for t in &text {
+      hasher.update(t.as_ref());
+    }
+    hasher.finalize()
+  };
 
   let fd = OpenOptions::new()
     .create_new(true)
@@ -69,7 +103,14 @@ pub async fn spit(
     .open(&tmp)
     .await
     .map_err(|e| Die::IO(tmp.clone(), e.kind()))?;
-  fd.set_permissions(meta.permissions())
+  let staged = Staged {
+    canonical: canonical.to_owned(),
+    permissions,
+    pre_image,
+    digest,
+    tmp: Some(tmp.clone()),
+  };
+  fd.set_permissions(staged.permissions.clone())
     .await
     .map_err(|e| Die::IO(tmp.clone(), e.kind()))?;
 
@@ -86,9 +127,56 @@ pub async fn spit(
     .await
     .map_err(|e| Die::IO(tmp.clone(), e.kind()))?;
 
-  rename(&tmp, &canonical)
-    .await
-    .map_err(|e| Die::IO(canonical.to_owned(), e.kind()))?;
+  Ok(staged)
+}
+
+async fn swap(mut staged: Staged) -> Result<(PathBuf, PathBuf), Die> {
+  let canonical = staged.canonical.clone();
+  let tmp = staged.tmp.clone().unwrap_or_default();
+  let backup = sibling(&canonical, ".bak");
+
+  if hard_link(&canonical, &backup).await.is_err() {
+    copy(&canonical, &backup)
+      .await
+      .map_err(|e| Die::IO(backup.clone(), e.kind()))?;
+  }
+  if let Err(e) = rename(&tmp, &canonical).await {
+    let _ = fs::remove_file(&backup).await;
+    return Err(Die::IO(canonical, e.kind()));
+  }
+  staged.tmp = None;
+
+  Ok((canonical, backup))
+}
 
-  Ok(())
+pub async fn commit(staged: Vec<Staged>) -> Result<(), Die> {
+  let mut swapped = Vec::new();
+  let mut err = None;
+
+  for s in staged {
+    match swap(s).await {
+      Ok(pair) => swapped.push(pair),
+      Err(e) => {
+        err = Some(e);
+        break;
+      }
+    }
+  }
+
+  match err {
+    None => {
+      for (_, backup) in swapped {
+        let _ = fs::remove_file(&backup).await;
+      }
+      Ok(())
+    }
+    Some(mut err) => {
+      for (canonical, backup) in swapped.into_iter().rev() {
+        if let Err(e) = rename(&backup, &canonical).await {
+          err = Die::IO(canonical, e.kind());
+        }
+      }
+      Err(err)
+    }
+  }
 }
@@ -0,0 +1,45 @@
+#[cfg(test)]
+mod spec {
+  use super::super::{
+    fixture::{block_on, listing, scratch, staged},
+    fs_pipe::commit,
+  };
+  use std::fs::{read_to_string, remove_dir_all, remove_file};
+
+  #[test]
+  fn commit_all() {
+    let dir = scratch(&[("a.txt", "a\n"), ("b.txt", "b\n")]);
+    block_on(async {
+      let staged = vec![
+        staged(&dir, "a.txt", "A\n").await,
+        staged(&dir, "b.txt", "B\n").await,
+      ];
+      commit(staged).await.unwrap();
+    });
+    assert_eq!(read_to_string(dir.join("a.txt")).unwrap(), "A\n");
+    assert_eq!(read_to_string(dir.join("b.txt")).unwrap(), "B\n");
+    assert_eq!(listing(&dir), ["a.txt", "b.txt"]);
+    remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn rollback() {
+    let dir = scratch(&[("a.txt", "a\n"), ("b.txt", "b\n")]);
+    block_on(async {
+      let staged = vec![
+        staged(&dir, "a.txt", "A\n").await,
+        staged(&dir, "b.txt", "B\n").await,
+      ];
+      let tmp = listing(&dir)
+        .into_iter()
+        .find(|name| name.starts_with("b.txt___"))
+        .unwrap();
+      remove_file(dir.join(tmp)).unwrap();
+      assert!(commit(staged).await.is_err());
+    });
+    assert_eq!(read_to_string(dir.join("a.txt")).unwrap(), "a\n");
+    assert_eq!(read_to_string(dir.join("b.txt")).unwrap(), "b\n");
+    assert_eq!(listing(&dir), ["a.txt", "b.txt"]);
+    remove_dir_all(dir).unwrap();
+  }
+}
@@ -1,15 +1,16 @@
 use {
   super::{
-    argparse::Mode,
+    argparse::{Live, Mode},
     subprocess::{stream_subproc, SubprocCommand},
     types::Die,
   },
   futures::stream::{Stream, StreamExt},
+  shlex::try_quote,
   std::{
     collections::HashMap,
-    env::{self, current_exe},
+    env::current_exe,
     ffi::OsString,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::Stdio,
   },
   tokio::{io::ErrorKind, process::Command},
@@ -44,12 +45,32 @@ async fn reset_term() -> Result<(), Die> {
   Err(Die::IO(PathBuf::from("reset"), ErrorKind::NotFound))
 }
 
+fn quote(arg: &str) -> String {
+  try_quote(arg).map_or_else(|_| arg.to_owned(), Into::into)
+}
+
 pub fn stream_fzf_proc(
   bin: PathBuf,
   args: Vec<String>,
+  live: Option<(Live, &str)>,
+  socket: &Path,
   stream: impl Stream<Item = Result<OsString, Die>> + Unpin,
 ) -> impl Stream<Item = Result<(), Die>> {
-  let execute = format!("abort+execute:{}\x04{{+f}}", Mode::PATCH);
+  let exe = current_exe()
+    .or_else(|_| which(env!("CARGO_PKG_NAME")))
+    .map_or_else(
+      |_| env!("CARGO_PKG_NAME").to_owned(),
+      |path| format!("{}", path.display()),
+    );
+  let client = |mode: &str, field: &str| {
+    format!(
+      "{} {} {} {field}",
+      quote(&exe),
+      quote(mode),
+      quote(&format!("{}", socket.display()))
+    )
+  };
+  let execute = format!("abort+execute:{}", client(Mode::PATCH, "{+f}"));
   let mut arguments = vec![
     "--read0".to_owned(),
     "--print0".to_owned(),
@@ -58,24 +79,26 @@ pub fn stream_fzf_proc(
     "--preview-window=70%:wrap".to_owned(),
     format!("--bind=enter:{execute}"),
     format!("--bind=double-click:{execute}"),
-    format!("--preview={}\x04{{+f}}", Mode::PREVIEW),
+    format!("--preview={}", client(Mode::PREVIEW, "{+f}")),
   ];
+  if let Some((live, query)) = live {
+    let prompt = match live {
+      Live::Pattern => "pattern",
+      Live::Replace => "replace",
+    };
+    arguments.extend([
+      "--disabled".to_owned(),
+      format!("--query={query}"),
+      format!("--prompt={prompt}> "),
+      format!("--bind=change:reload:{}", client(Mode::RELOAD, "{q}")),
+    ]);
+  }
   arguments.extend(args);
 
   let mut fzf_env = HashMap::new();
-  fzf_env.insert(
-    Mode::ARGV.to_owned(),
-    env::args().collect::<Vec<_>>().join("\x04"),
-  );
-  fzf_env.insert(
-    "SHELL".to_owned(),
-    current_exe()
-      .or_else(|_| which(env!("CARGO_PKG_NAME")))
-      .map_or_else(
-        |_| env!("CARGO_PKG_NAME").to_owned(),
-        |path| format!("{}", path.display()),
-      ),
-  );
+  if let Ok(sh) = which("sh") {
+    fzf_env.insert("SHELL".to_owned(), format!("{}", sh.display()));
+  }
   fzf_env.insert("LC_ALL".to_owned(), "C.UTF-8".to_owned());
 
   let cmd = SubprocCommand {
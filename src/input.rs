@@ -1,22 +1,25 @@
 use {
-  super::{
-    argparse::{Arguments, Mode},
-    types::Die,
-    udiff::DiffRange,
-  },
+  super::{argparse::Arguments, types::Die, udiff::DiffRange},
   futures::{
+    channel::mpsc::channel,
+    executor::block_on,
     future::{ready, Either},
     stream::{once, try_unfold, Stream, TryStreamExt},
+    SinkExt,
   },
+  ignore::{overrides::OverrideBuilder, types::TypesBuilder, Walk, WalkBuilder},
   regex::Regex,
   std::{
-    collections::HashSet,
+    borrow::ToOwned,
+    collections::{HashMap, HashSet},
+    env::current_dir,
     ffi::OsString,
     io::{self, ErrorKind, IsTerminal},
     path::{Path, PathBuf},
+    thread::spawn,
   },
   tokio::{
-    fs::{canonicalize, File},
+    fs::canonicalize,
     io::{stdin, AsyncBufReadExt, BufReader},
   },
 };
@@ -24,16 +27,20 @@ use {
 #[derive(Debug)]
 pub enum RowIn {
   Entire(PathBuf),
-  Piecewise(PathBuf, HashSet<DiffRange>),
+  Piecewise(
+    PathBuf,
+    HashMap<DiffRange, String>,
+    HashMap<String, Vec<String>>,
+  ),
 }
 
 #[derive(Debug)]
-struct DiffRow(PathBuf, DiffRange);
+struct DiffRow(PathBuf, DiffRange, String);
 
 fn p_row(row: &str) -> Result<DiffRow, Die> {
   let f = || Die::ArgumentError(String::new());
   let ff = |_| f();
-  let preg = "\n\n\n\n@@ -(\\d+),(\\d+) \\+(\\d+),(\\d+) @@$";
+  let preg = "\n\n\n\n@@ -(\\d+),(\\d+) \\+(\\d+),(\\d+) @@(?: :(\\d+))? ([[:xdigit:]]+)(?:\n.*)*$";
   let re = Regex::new(preg).map_err(Die::RegexError)?;
   let captures = re.captures(row).ok_or_else(f)?;
 
@@ -62,70 +69,47 @@ fn p_row(row: &str) -> Result<DiffRow, Die> {
     .parse::<usize>()
     .map_err(ff)?;
 
+  let column = match captures.get(5) {
+    Some(column) => Some(
+      column
+        .as_str()
+        .parse::<usize>()
+        .map_err(ff)?
+        .checked_sub(1)
+        .ok_or_else(f)?,
+    ),
+    None => None,
+  };
+  let digest = captures.get(6).ok_or_else(f)?.as_str().to_owned();
+
   let range = DiffRange {
     before: (before_start - 1, before_inc),
     after: (after_start - 1, after_inc),
+    column,
   };
   let path = PathBuf::from(String::from(re.replace(row, "")));
-  Ok(DiffRow(path, range))
+  Ok(DiffRow(path, range, digest))
 }
 
-async fn stream_patch(patches: &Path) -> impl Stream<Item = Result<RowIn, Die>> {
-  let patches = patches.to_owned();
-
-  let fd = match File::open(&patches).await {
-    Err(e) => {
-      let err = Die::IO(patches.clone(), e.kind());
-      return Either::Left(once(ready(Err(err))));
-    }
-    Ok(fd) => fd,
-  };
-  let reader = BufReader::new(fd).split(b'\0');
-  let acc = HashSet::new();
-
-  let stream = try_unfold(
-    (reader, patches, PathBuf::new(), acc),
-    move |mut s| async move {
-      let next = s
-        .0
-        .next_segment()
-        .await
-        .map_err(|e| Die::IO(s.1.clone(), e.kind()))?;
-
-      match next {
-        None if s.3.is_empty() => Ok(None),
-        None => {
-          let path = s.2;
-          let ranges = s.3;
-          s.2 = PathBuf::new();
-          s.3 = HashSet::new();
-          Ok(Some((Some(RowIn::Piecewise(path, ranges)), s)))
-        }
-        Some(buf) => {
-          let row =
-            String::from_utf8(buf).map_err(|_| Die::IO(s.1.clone(), ErrorKind::InvalidData))?;
-          let parsed = p_row(&row)?;
-          if parsed.0 == s.2 {
-            s.3.insert(parsed.1);
-            Ok(Some((None, s)))
-          } else {
-            let path = s.2;
-            let ranges = s.3;
-            s.2 = parsed.0;
-            s.3 = HashSet::new();
-            s.3.insert(parsed.1);
-            if ranges.is_empty() {
-              Ok(Some((None, s)))
-            } else {
-              Ok(Some((Some(RowIn::Piecewise(path, ranges)), s)))
-            }
-          }
-        }
+pub fn p_selection(selection: &[u8]) -> Result<Vec<RowIn>, Die> {
+  let mut acc = Vec::<(PathBuf, HashMap<DiffRange, String>)>::new();
+  for buf in selection.split(|b| *b == b'\0').filter(|b| !b.is_empty()) {
+    let row = std::str::from_utf8(buf)
+      .map_err(|_| Die::IO(PathBuf::from("selection"), ErrorKind::InvalidData))?;
+    let DiffRow(path, range, digest) = p_row(row)?;
+    match acc.iter_mut().find(|(p, _)| *p == path) {
+      Some((_, ranges)) => {
+        ranges.insert(range, digest);
       }
-    },
-  );
-
-  Either::Right(stream.try_filter_map(|x| ready(Ok(x))))
+      None => acc.push((path, HashMap::from([(range, digest)]))),
+    }
+  }
+  Ok(
+    acc
+      .into_iter()
+      .map(|(path, ranges)| RowIn::Piecewise(path, ranges, HashMap::new()))
+      .collect(),
+  )
 }
 
 fn u8_pathbuf(v8: Vec<u8>) -> PathBuf {
@@ -147,46 +131,143 @@ fn u8_pathbuf(v8: Vec<u8>) -> PathBuf {
   }
 }
 
-fn stream_stdin(use_nul: bool) -> impl Stream<Item = Result<RowIn, Die>> {
-  if io::stdin().is_terminal() {
-    let err = Die::ArgumentError("/dev/stdin connected to tty".to_owned());
-    return Either::Left(once(ready(Err(err))));
-  }
+fn stream_stdin(use_nul: bool) -> impl Stream<Item = Result<PathBuf, Die>> {
   let delim = if use_nul { b'\0' } else { b'\n' };
   let reader = BufReader::new(stdin()).split(delim);
-  let seen = HashSet::new();
 
-  let stream = try_unfold((reader, seen), |mut s| async {
-    let next = s
-      .0
+  try_unfold(reader, |mut reader| async {
+    let next = reader
       .next_segment()
       .await
       .map_err(|e| Die::IO(PathBuf::from("/dev/stdin"), e.kind()))?;
-    match next {
-      None => Ok(None),
-      Some(buf) => {
-        let path = u8_pathbuf(buf);
-        match canonicalize(&path).await {
-          Err(e) if e.kind() == ErrorKind::NotFound => Ok(Some((None, s))),
-          Err(e) => Err(Die::IO(path, e.kind())),
-          Ok(canonical) => Ok(Some({
-            if s.1.insert(canonical.clone()) {
-              (Some(RowIn::Entire(canonical)), s)
-            } else {
-              (None, s)
-            }
-          })),
-        }
+    Ok(next.map(|buf| (u8_pathbuf(buf), reader)))
+  })
+}
+
+fn p_walk_err(err: &ignore::Error) -> Option<Die> {
+  fn path_of(err: &ignore::Error) -> Option<&Path> {
+    match err {
+      ignore::Error::WithPath { path, .. } => Some(path),
+      ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+        path_of(err)
+      }
+      _ => None,
+    }
+  }
+  match err.io_error().map(io::Error::kind) {
+    Some(ErrorKind::NotFound) => None,
+    Some(kind) => Some(Die::IO(
+      path_of(err).map(ToOwned::to_owned).unwrap_or_default(),
+      kind,
+    )),
+    None => Some(Die::ArgumentError(format!("{err}"))),
+  }
+}
+
+pub fn p_walker(args: &Arguments) -> Result<Walk, Die> {
+  let cwd = current_dir().map_err(|e| Die::IO(PathBuf::from("."), e.kind()))?;
+  let mut roots = args.paths.iter();
+  let mut walker = WalkBuilder::new(roots.next().map_or_else(|| Path::new("."), AsRef::as_ref));
+  for root in roots {
+    walker.add(root);
+  }
+
+  let mut types = TypesBuilder::new();
+  types.add_defaults();
+  for t in &args.file_type {
+    match t.strip_prefix('!') {
+      Some(name) => types.negate(name),
+      None => types.select(t),
+    };
+  }
+
+  let mut overrides = OverrideBuilder::new(cwd);
+  for glob in &args.glob {
+    overrides
+      .add(glob)
+      .map_err(|e| Die::ArgumentError(format!("{e}")))?;
+  }
+
+  walker
+    .standard_filters(!args.no_ignore)
+    .hidden(!args.hidden)
+    // `--glob` `--type` whitelists would otherwise let hidden entries through
+    .filter_entry({
+      let hidden = args.hidden;
+      move |entry| {
+        hidden || entry.depth() == 0 || !entry.file_name().as_encoded_bytes().starts_with(b".")
+      }
+    })
+    .max_depth(args.max_depth)
+    .types(
+      types
+        .build()
+        .map_err(|e| Die::ArgumentError(format!("{e}")))?,
+    )
+    .overrides(
+      overrides
+        .build()
+        .map_err(|e| Die::ArgumentError(format!("{e}")))?,
+    );
+  Ok(walker.build())
+}
+
+fn stream_walk(args: &Arguments) -> impl Stream<Item = Result<PathBuf, Die>> {
+  let walker = match p_walker(args) {
+    Err(e) => return Either::Left(once(ready(Err(e)))),
+    Ok(walker) => walker,
+  };
+  let (mut tx, rx) = channel(1024);
+
+  spawn(move || {
+    for entry in walker {
+      let row = match entry {
+        Ok(entry) if entry.file_type().is_some_and(|t| t.is_file()) => Ok(entry.into_path()),
+        Ok(_) => continue,
+        Err(e) => match p_walk_err(&e) {
+          None => continue,
+          Some(e) => Err(e),
+        },
+      };
+      if block_on(tx.send(row)).is_err() {
+        break;
       }
     }
   });
 
-  Either::Right(stream.try_filter_map(|x| ready(Ok(x))))
+  Either::Right(rx)
+}
+
+fn stream_canonical(
+  paths: impl Stream<Item = Result<PathBuf, Die>>,
+) -> impl Stream<Item = Result<RowIn, Die>> {
+  let mut seen = HashSet::new();
+
+  paths
+    .and_then(|path| async move {
+      match canonicalize(&path).await {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Die::IO(path, e.kind())),
+        Ok(canonical) => Ok(Some(canonical)),
+      }
+    })
+    .try_filter_map(move |canonical| {
+      let row = canonical
+        .filter(|c| seen.insert(c.clone()))
+        .map(RowIn::Entire);
+      ready(Ok(row))
+    })
+}
+
+/// Piped paths win unless paths are given, a tty on stdin means walk `.`
+pub const fn reads_stdin(args: &Arguments, tty: bool) -> bool {
+  args.paths.is_empty() && !tty
 }
 
-pub async fn stream_in(mode: &Mode, args: &Arguments) -> impl Stream<Item = Result<RowIn, Die>> {
-  match mode {
-    Mode::Initial => Either::Left(stream_stdin(args.read0)),
-    Mode::Preview(path) | Mode::Patch(path) => Either::Right(stream_patch(path).await),
+pub fn stream_in(args: &Arguments) -> impl Stream<Item = Result<RowIn, Die>> {
+  if reads_stdin(args, io::stdin().is_terminal()) {
+    Either::Left(stream_canonical(stream_stdin(args.read0)))
+  } else {
+    Either::Right(stream_canonical(stream_walk(args)))
   }
 }
@@ -0,0 +1,79 @@
+#[cfg(test)]
+mod spec {
+  use super::super::{
+    argparse::Arguments,
+    fixture::scratch,
+    input::{p_walker, reads_stdin},
+  };
+  use clap::Parser;
+  use std::{fs::remove_dir_all, path::Path};
+
+  fn walk(dir: &Path, flags: &[&str]) -> Vec<String> {
+    let root = dir.to_string_lossy();
+    let head = ["sad", "foo", "bar", &root];
+    let args = Arguments::parse_from(head.iter().chain(flags));
+    let mut files = p_walker(&args)
+      .unwrap()
+      .map(Result::unwrap)
+      .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+      .map(|entry| {
+        let path = entry.path().strip_prefix(dir).unwrap();
+        path.to_string_lossy().replace('\\', "/")
+      })
+      .collect::<Vec<_>>();
+    files.sort();
+    files
+  }
+
+  #[test]
+  fn walker() {
+    let dir = scratch(&[
+      ("a.rs", ""),
+      ("b.txt", ""),
+      (".hidden.rs", ""),
+      (".ignore", "skip.rs\n"),
+      ("skip.rs", ""),
+      ("sub/c.rs", ""),
+      ("sub/deep/d.rs", ""),
+    ]);
+    let all = ["a.rs", "b.txt", "sub/c.rs", "sub/deep/d.rs"];
+    assert_eq!(walk(&dir, &[]), all);
+    assert_eq!(
+      walk(&dir, &["--glob", "*.rs"]),
+      ["a.rs", "skip.rs", "sub/c.rs", "sub/deep/d.rs"],
+      "globs override ignore files, like rg"
+    );
+    assert_eq!(walk(&dir, &["--glob", "!*.rs"]), ["b.txt"]);
+    assert_eq!(walk(&dir, &["--type", "!rust"]), ["b.txt"]);
+    assert_eq!(
+      walk(&dir, &["--type", "rust"]),
+      ["a.rs", "sub/c.rs", "sub/deep/d.rs"]
+    );
+    assert_eq!(
+      walk(&dir, &["--hidden"]),
+      [
+        ".hidden.rs",
+        ".ignore",
+        "a.rs",
+        "b.txt",
+        "sub/c.rs",
+        "sub/deep/d.rs"
+      ]
+    );
+    assert_eq!(
+      walk(&dir, &["--no-ignore"]),
+      ["a.rs", "b.txt", "skip.rs", "sub/c.rs", "sub/deep/d.rs"]
+    );
+    assert_eq!(walk(&dir, &["--max-depth", "1"]), ["a.rs", "b.txt"]);
+    remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn stdin_or_walk() {
+    let piped = Arguments::parse_from(["sad", "foo", "bar"]);
+    assert!(reads_stdin(&piped, false));
+    assert!(!reads_stdin(&piped, true));
+    let given = Arguments::parse_from(["sad", "foo", "bar", "src"]);
+    assert!(!reads_stdin(&given, false));
+  }
+}
@@ -0,0 +1,316 @@
+use {
+  super::{
+    argparse::{Live, Options, Printer},
+    displace::{apply, displace, Diffed, Displaced},
+    input::{p_selection, RowIn},
+    subprocess::SubprocCommand,
+    types::Die,
+  },
+  futures::{
+    future::ready,
+    stream::{iter, TryStreamExt},
+  },
+  std::{
+    collections::{HashMap, HashSet},
+    env::temp_dir,
+    ffi::OsString,
+    fs::{remove_dir_all, DirBuilder},
+    io::{self, ErrorKind},
+    os::unix::{ffi::OsStringExt, fs::DirBuilderExt},
+    path::{Path, PathBuf},
+    sync::{
+      atomic::{AtomicUsize, Ordering},
+      Arc, Mutex,
+    },
+  },
+  tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{UnixListener, UnixStream},
+    spawn,
+  },
+  uuid::Uuid,
+};
+
+const PREVIEW: u8 = 1;
+const PATCH: u8 = 2;
+const PRINT: u8 = 3;
+const FAIL: u8 = 4;
+const RELOAD: u8 = 5;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Request {
+  Preview(Vec<u8>),
+  Patch(Vec<u8>),
+  Reload(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub enum Response {
+  Print(Option<SubprocCommand>, Vec<u8>),
+  Fail(String),
+}
+
+pub struct Session {
+  opts: Mutex<Arc<Options>>,
+  cache: Mutex<HashMap<OsString, Diffed>>,
+  paths: Mutex<Vec<PathBuf>>,
+  listing: Mutex<Vec<u8>>,
+  generation: AtomicUsize,
+  threads: usize,
+}
+
+async fn write_frame(writer: &mut (impl AsyncWrite + Unpin), frame: &[u8]) -> io::Result<()> {
+  writer.write_u64(frame.len() as u64).await?;
+  writer.write_all(frame).await
+}
+
+async fn read_frame(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+  let len = usize::try_from(reader.read_u64().await?)
+    .map_err(|_| io::Error::from(ErrorKind::InvalidData))?;
+  let mut frame = vec![0; len];
+  reader.read_exact(&mut frame).await?;
+  Ok(frame)
+}
+
+pub async fn write_request(
+  writer: &mut (impl AsyncWrite + Unpin),
+  request: &Request,
+) -> io::Result<()> {
+  let (tag, selection) = match request {
+    Request::Preview(selection) => (PREVIEW, selection),
+    Request::Patch(selection) => (PATCH, selection),
+    Request::Reload(query) => (RELOAD, query),
+  };
+  writer.write_u8(tag).await?;
+  write_frame(writer, selection).await?;
+  writer.flush().await
+}
+
+pub async fn read_request(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Request> {
+  match reader.read_u8().await? {
+    PREVIEW => Ok(Request::Preview(read_frame(reader).await?)),
+    PATCH => Ok(Request::Patch(read_frame(reader).await?)),
+    RELOAD => Ok(Request::Reload(read_frame(reader).await?)),
+    _ => Err(io::Error::from(ErrorKind::InvalidData)),
+  }
+}
+
+pub async fn write_response(
+  writer: &mut (impl AsyncWrite + Unpin),
+  response: &Response,
+) -> io::Result<()> {
+  match response {
+    Response::Print(pager, print) => {
+      let pager = serde_json::to_vec(pager).map_err(io::Error::from)?;
+      writer.write_u8(PRINT).await?;
+      write_frame(writer, &pager).await?;
+      write_frame(writer, print).await?;
+    }
+    Response::Fail(msg) => {
+      writer.write_u8(FAIL).await?;
+      write_frame(writer, msg.as_bytes()).await?;
+    }
+  }
+  writer.flush().await
+}
+
+pub async fn read_response(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Response> {
+  match reader.read_u8().await? {
+    PRINT => {
+      let pager = serde_json::from_slice(&read_frame(reader).await?).map_err(io::Error::from)?;
+      Ok(Response::Print(pager, read_frame(reader).await?))
+    }
+    FAIL => {
+      let msg = String::from_utf8(read_frame(reader).await?)
+        .map_err(|_| io::Error::from(ErrorKind::InvalidData))?;
+      Ok(Response::Fail(msg))
+    }
+    _ => Err(io::Error::from(ErrorKind::InvalidData)),
+  }
+}
+
+pub async fn request(socket: &Path, request: &Request) -> Result<Response, Die> {
+  let io_err = |e: io::Error| Die::IO(socket.to_owned(), e.kind());
+  let mut stream = UnixStream::connect(socket).await.map_err(io_err)?;
+  write_request(&mut stream, request).await.map_err(io_err)?;
+  read_response(&mut stream).await.map_err(io_err)
+}
+
+pub struct Server {
+  dir: PathBuf,
+  listener: UnixListener,
+}
+
+impl Drop for Server {
+  fn drop(&mut self) {
+    let _ = remove_dir_all(&self.dir);
+  }
+}
+
+pub fn bind() -> Result<Server, Die> {
+  let uuid = Uuid::new_v4().as_simple().to_string();
+  let dir = temp_dir().join(format!("{}-{uuid}", env!("CARGO_PKG_NAME")));
+  DirBuilder::new()
+    .mode(0o700)
+    .create(&dir)
+    .map_err(|e| Die::IO(dir.clone(), e.kind()))?;
+  let socket = dir.join("ipc.sock");
+  match UnixListener::bind(&socket) {
+    Ok(listener) => Ok(Server { dir, listener }),
+    Err(e) => {
+      let _ = remove_dir_all(&dir);
+      Err(Die::IO(socket, e.kind()))
+    }
+  }
+}
+
+impl Session {
+  pub fn new(opts: Arc<Options>, threads: usize) -> Self {
+    Self {
+      opts: Mutex::new(opts),
+      cache: Mutex::default(),
+      paths: Mutex::default(),
+      listing: Mutex::default(),
+      generation: AtomicUsize::new(0),
+      threads,
+    }
+  }
+
+  fn opts(&self) -> Arc<Options> {
+    self.opts.lock().expect("poisoned session").clone()
+  }
+
+  pub fn track(&self, path: &Path) {
+    if self.opts().live.is_some() {
+      let mut paths = self.paths.lock().expect("poisoned session");
+      paths.push(path.to_owned());
+    }
+  }
+
+  pub fn insert(&self, diffed: Diffed) {
+    if self.generation.load(Ordering::SeqCst) == 0 {
+      let mut cache = self.cache.lock().expect("poisoned session");
+      cache.insert(diffed.name.clone(), diffed);
+    }
+  }
+
+  async fn reload(&self, query: &str) -> Result<Vec<u8>, Die> {
+    let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+    let prev = self.opts();
+    if query.is_empty() && matches!(prev.live, Some(Live::Pattern)) {
+      self.cache.lock().expect("poisoned session").clear();
+      self.listing.lock().expect("poisoned session").clear();
+      return Ok(Vec::new());
+    }
+    let Ok(opts) = prev.with_query(query) else {
+      return Ok(self.listing.lock().expect("poisoned session").clone());
+    };
+
+    let paths = self.paths.lock().expect("poisoned session").clone();
+    let mut listed = iter(paths.into_iter().map(|path| Ok(RowIn::Entire(path))))
+      .map_ok(|row| displace(&opts, row))
+      .try_buffer_unordered(opts.lanes(self.threads))
+      .try_filter_map(|displaced| {
+        ready(Ok(match displaced {
+          Displaced::Listed(print, diffed) => Some((print, diffed)),
+          _ => None,
+        }))
+      })
+      .try_collect::<Vec<_>>()
+      .await?;
+    listed.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+
+    let mut listing = OsString::new();
+    let mut cache = HashMap::new();
+    for (print, diffed) in listed {
+      listing.push(print);
+      cache.insert(diffed.name.clone(), diffed);
+    }
+    let listing = listing.into_vec();
+    if self.generation.load(Ordering::SeqCst) == generation {
+      *self.opts.lock().expect("poisoned session") = Arc::new(opts);
+      *self.cache.lock().expect("poisoned session") = cache;
+      self
+        .listing
+        .lock()
+        .expect("poisoned session")
+        .clone_from(&listing);
+    }
+    Ok(listing)
+  }
+
+  fn preview(&self, selection: &[u8]) -> Result<Response, Die> {
+    let opts = self.opts();
+    let mut print = OsString::new();
+    let cache = self.cache.lock().expect("poisoned session");
+    for row in p_selection(selection)? {
+      if let RowIn::Piecewise(name, picks, _) = row {
+        if let Some(diffed) = cache.get(name.as_os_str()) {
+          let ranges = picks.into_keys().collect::<HashSet<_>>();
+          print.push(diffed.render(opts.unified, Some(&ranges)));
+        }
+      }
+    }
+    let pager = match &opts.printer {
+      Printer::Pager(cmd) => Some(cmd.clone()),
+      Printer::Stdout => None,
+    };
+    Ok(Response::Print(pager, print.into_vec()))
+  }
+
+  async fn patch(&self, selection: &[u8]) -> Result<Response, Die> {
+    let rows = {
+      let cache = self.cache.lock().expect("poisoned session");
+      p_selection(selection)?
+        .into_iter()
+        .map(|row| match row {
+          RowIn::Piecewise(name, picks, edits) => {
+            let path = cache
+              .get(name.as_os_str())
+              .map_or(name, |diffed| diffed.path.clone());
+            RowIn::Piecewise(path, picks, edits)
+          }
+          row @ RowIn::Entire(_) => row,
+        })
+        .collect::<Vec<_>>()
+    };
+    let names = apply(&self.opts(), rows).await?;
+    let print = names.into_iter().flat_map(OsString::into_vec).collect();
+    Ok(Response::Print(None, print))
+  }
+
+  async fn handle(&self, mut stream: UnixStream) -> Result<(), Die> {
+    let io_err = |e: io::Error| Die::IO(PathBuf::from("ipc"), e.kind());
+    let req = read_request(&mut stream).await.map_err(io_err)?;
+    let resp = match req {
+      Request::Preview(selection) => self.preview(&selection),
+      Request::Patch(selection) => self.patch(&selection).await,
+      Request::Reload(query) => self
+        .reload(&String::from_utf8_lossy(&query))
+        .await
+        .map(|listing| Response::Print(None, listing)),
+    }
+    .unwrap_or_else(|e| Response::Fail(format!("{e}")));
+    write_response(&mut stream, &resp).await.map_err(io_err)
+  }
+}
+
+impl Server {
+  pub fn socket(&self) -> PathBuf {
+    self.dir.join("ipc.sock")
+  }
+
+  pub async fn serve(&self, session: Arc<Session>) -> Die {
+    loop {
+      match self.listener.accept().await {
+        Err(e) => return Die::IO(self.socket(), e.kind()),
+        Ok((stream, _)) => {
+          let session = session.clone();
+          spawn(async move {
+            let _ = session.handle(stream).await;
+          });
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod spec {
+  use super::super::{
+    fixture::block_on,
+    ipc::{read_request, read_response, write_request, write_response, Request, Response},
+    subprocess::SubprocCommand,
+  };
+  use std::{collections::HashMap, path::PathBuf};
+  use tokio::io::duplex;
+
+  #[test]
+  fn requests() {
+    let requests = vec![
+      Request::Preview(b"a.txt\n\n\n\n@@ -1,2 +1,2 @@ 0123456789abcdef\0".to_vec()),
+      Request::Patch(b"\x04\x04\0".to_vec()),
+      Request::Preview(Vec::new()),
+    ];
+    block_on(async {
+      for request in requests {
+        let (mut client, mut server) = duplex(8);
+        let (sent, received) = futures::join!(
+          write_request(&mut client, &request),
+          read_request(&mut server)
+        );
+        sent.unwrap();
+        assert_eq!(received.unwrap(), request);
+      }
+    });
+  }
+
+  #[test]
+  fn responses() {
+    let pager = SubprocCommand {
+      prog: PathBuf::from("delta"),
+      args: vec!["-s".to_owned()],
+      env: HashMap::new(),
+    };
+    block_on(async {
+      let (mut client, mut server) = duplex(8);
+      let response = Response::Print(Some(pager), b"diff\n\0\xff".to_vec());
+      let (sent, received) = futures::join!(
+        write_response(&mut server, &response),
+        read_response(&mut client)
+      );
+      sent.unwrap();
+      match received.unwrap() {
+        Response::Print(Some(cmd), print) => {
+          assert_eq!(cmd.prog, PathBuf::from("delta"));
+          assert_eq!(cmd.args, vec!["-s".to_owned()]);
+          assert_eq!(print, b"diff\n\0\xff".to_vec());
+        }
+        r => panic!("{r:?}"),
+      }
+
+      let (mut client, mut server) = duplex(8);
+      let response = Response::Fail("Error: owo".to_owned());
+      let (sent, received) = futures::join!(
+        write_response(&mut server, &response),
+        read_response(&mut client)
+      );
+      sent.unwrap();
+      assert!(matches!(received.unwrap(), Response::Fail(e) if e == "Error: owo"));
+    });
+  }
+}
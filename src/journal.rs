@@ -0,0 +1,294 @@
+use {
+  super::{
+    argparse::Options,
+    fs_pipe::{commit, stage, Staged},
+    types::Die,
+  },
+  ansi_term::Colour,
+  serde::{Deserialize, Serialize},
+  std::{
+    env::var_os,
+    ffi::OsString,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+  },
+  tokio::fs::{create_dir_all, metadata, read, read_dir, remove_dir_all, write},
+  uuid::Uuid,
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Record {
+  path: PathBuf,
+  readonly: bool,
+  mode: Option<u32>,
+  post: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Manifest {
+  id: String,
+  time: u64,
+  cwd: Option<PathBuf>,
+  pattern: String,
+  replace: String,
+  #[serde(default)]
+  exprs: Vec<(String, String)>,
+  files: Vec<Record>,
+}
+
+pub struct Journal {
+  dir: PathBuf,
+}
+
+const MANIFEST: &str = "manifest.json";
+const UNDONE: &str = "undone";
+const KEEP_RUNS: usize = 64;
+const KEEP_BYTES: u64 = 256 * 1024 * 1024;
+
+fn state_dir() -> Option<PathBuf> {
+  var_os("XDG_STATE_HOME")
+    .map(PathBuf::from)
+    .or_else(|| var_os("LOCALAPPDATA").map(PathBuf::from))
+    .or_else(|| var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))
+    .map(|state| state.join(env!("CARGO_PKG_NAME")))
+}
+
+async fn load(dir: &Path) -> Result<Manifest, Die> {
+  let path = dir.join(MANIFEST);
+  let json = read(&path)
+    .await
+    .map_err(|e| Die::IO(path.clone(), e.kind()))?;
+  serde_json::from_slice(&json).map_err(|_| Die::IO(path, ErrorKind::InvalidData))
+}
+
+async fn runs(state: &Path) -> Result<Vec<PathBuf>, Die> {
+  let mut dirs = Vec::new();
+  let mut entries = match read_dir(state).await {
+    Err(e) if e.kind() == ErrorKind::NotFound => return Ok(dirs),
+    Err(e) => return Err(Die::IO(state.to_owned(), e.kind())),
+    Ok(entries) => entries,
+  };
+  while let Some(entry) = entries
+    .next_entry()
+    .await
+    .map_err(|e| Die::IO(state.to_owned(), e.kind()))?
+  {
+    if entry.path().join(MANIFEST).exists() {
+      dirs.push(entry.path());
+    }
+  }
+  dirs.sort();
+  Ok(dirs)
+}
+
+async fn size(dir: &Path) -> u64 {
+  let mut total = 0;
+  if let Ok(mut entries) = read_dir(dir).await {
+    while let Ok(Some(entry)) = entries.next_entry().await {
+      total += entry.metadata().await.map_or(0, |meta| meta.len());
+    }
+  }
+  total
+}
+
+/// Drop the oldest runs past `KEEP_RUNS` or `KEEP_BYTES`, the newest run is always kept
+async fn prune(state: &Path) {
+  let Ok(dirs) = runs(state).await else {
+    return;
+  };
+  let mut kept = 0;
+  let mut bytes = 0;
+  for dir in dirs.into_iter().rev() {
+    bytes += size(&dir).await;
+    kept += 1;
+    if kept > 1 && (kept > KEEP_RUNS || bytes > KEEP_BYTES) {
+      let _ = remove_dir_all(&dir).await;
+    }
+  }
+}
+
+/// Journaling is best-effort, a failure is reported and the run goes on without undo
+pub async fn record(opts: &Options, staged: &[Staged]) -> Option<Journal> {
+  let state = state_dir()?;
+  if !opts.journal || staged.is_empty() {
+    return None;
+  }
+  match write_journal(opts, &state, staged).await {
+    Ok(journal) => {
+      prune(&state).await;
+      Some(journal)
+    }
+    Err(e) => {
+      let msg = format!("Warning: not journaled, cannot be undone :: {e}");
+      eprintln!("{}", Colour::Yellow.paint(msg));
+      None
+    }
+  }
+}
+
+async fn write_journal(opts: &Options, state: &Path, staged: &[Staged]) -> Result<Journal, Die> {
+  let time = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_secs();
+  let uuid = Uuid::new_v4().as_simple().to_string();
+  let id = format!("{time}-{}", &uuid[..8]);
+  let dir = state.join(&id);
+  create_dir_all(&dir)
+    .await
+    .map_err(|e| Die::IO(dir.clone(), e.kind()))?;
+  let journal = Journal { dir };
+
+  let mut files = Vec::new();
+  for (idx, s) in staged.iter().enumerate() {
+    let blob = journal.dir.join(idx.to_string());
+    if let Err(e) = write(&blob, &s.pre_image).await {
+      journal.discard().await;
+      return Err(Die::IO(blob, e.kind()));
+    }
+    #[cfg(target_family = "unix")]
+    let mode = {
+      use std::os::unix::fs::PermissionsExt;
+      Some(s.permissions.mode())
+    };
+    #[cfg(target_family = "windows")]
+    let mode = None;
+    files.push(Record {
+      path: s.canonical.clone(),
+      readonly: s.permissions.readonly(),
+      mode,
+      post: s.digest.to_hex().to_string(),
+    });
+  }
+
+  let mut exprs = opts.exprs.clone();
+  let (pattern, replace) = if exprs.is_empty() {
+    Default::default()
+  } else {
+    exprs.remove(0)
+  };
+  let manifest = Manifest {
+    id,
+    time,
+    cwd: opts.cwd.clone(),
+    pattern,
+    replace,
+    exprs,
+    files,
+  };
+  let path = journal.dir.join(MANIFEST);
+  let json = serde_json::to_vec_pretty(&manifest).expect("serializable manifest");
+  if let Err(e) = write(&path, json).await {
+    journal.discard().await;
+    return Err(Die::IO(path, e.kind()));
+  }
+
+  Ok(journal)
+}
+
+impl Journal {
+  pub async fn discard(&self) {
+    let _ = remove_dir_all(&self.dir).await;
+  }
+}
+
+pub async fn history() -> Result<Vec<OsString>, Die> {
+  let state = state_dir().ok_or_else(|| Die::ArgumentError("no state directory".to_owned()))?;
+  let mut lines = Vec::new();
+
+  for dir in runs(&state).await?.into_iter().rev() {
+    let manifest = load(&dir).await?;
+    let time = humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(manifest.time));
+    let undone = if dir.join(UNDONE).exists() {
+      " (undone)"
+    } else {
+      ""
+    };
+    let mut line = OsString::from(format!(
+      "{id}  {time}  {pattern:?} -> {replace:?}",
+      id = manifest.id,
+      pattern = manifest.pattern,
+      replace = manifest.replace,
+    ));
+    for (pattern, replace) in &manifest.exprs {
+      line.push(format!(" ; {pattern:?} -> {replace:?}"));
+    }
+    line.push(format!("{undone}\n"));
+    for file in manifest.files {
+      line.push("    ");
+      line.push(file.path.as_os_str());
+      line.push("\n");
+    }
+    lines.push(line);
+  }
+
+  Ok(lines)
+}
+
+pub async fn undo(id: Option<&str>) -> Result<Vec<OsString>, Die> {
+  let state = state_dir().ok_or_else(|| Die::ArgumentError("no state directory".to_owned()))?;
+  let runs = runs(&state).await?;
+  let dir = match id {
+    Some(id) => runs
+      .into_iter()
+      .find(|dir| dir.file_name().is_some_and(|name| name == id))
+      .ok_or_else(|| Die::ArgumentError(format!("no such run :: {id}")))?,
+    None => runs
+      .into_iter()
+      .rev()
+      .find(|dir| !dir.join(UNDONE).exists())
+      .ok_or_else(|| Die::ArgumentError("nothing to undo".to_owned()))?,
+  };
+  if dir.join(UNDONE).exists() {
+    return Err(Die::ArgumentError(format!(
+      "already undone :: {}",
+      dir.display()
+    )));
+  }
+  let manifest = load(&dir).await?;
+
+  let mut staged = Vec::new();
+  let mut names = Vec::new();
+  for (idx, file) in manifest.files.into_iter().enumerate() {
+    let current = read(&file.path)
+      .await
+      .map_err(|e| Die::IO(file.path.clone(), e.kind()))?;
+    if blake3::hash(&current).to_hex().as_str() != file.post {
+      return Err(Die::ArgumentError(format!(
+        "changed since {id}, refusing to undo :: {path}",
+        id = manifest.id,
+        path = file.path.display()
+      )));
+    }
+
+    let blob = dir.join(idx.to_string());
+    let pre_image = read(&blob)
+      .await
+      .map_err(|e| Die::IO(blob.clone(), e.kind()))?;
+    let mut permissions = metadata(&file.path)
+      .await
+      .map_err(|e| Die::IO(file.path.clone(), e.kind()))?
+      .permissions();
+    #[cfg(target_family = "windows")]
+    permissions.set_readonly(file.readonly);
+    #[cfg(target_family = "unix")]
+    if let Some(mode) = file.mode {
+      use std::os::unix::fs::PermissionsExt;
+      permissions.set_mode(mode);
+    }
+
+    staged.push(stage(&file.path, permissions, current, vec![pre_image]).await?);
+    let mut name = file.path.into_os_string();
+    name.push("\n");
+    names.push(name);
+  }
+
+  commit(staged).await?;
+  let marker = dir.join(UNDONE);
+  write(&marker, b"")
+    .await
+    .map_err(|e| Die::IO(marker, e.kind()))?;
+
+  Ok(names)
+}
@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod spec {
+  use super::super::{
+    argparse::{parse_opts, Arguments},
+    fixture::{block_on, scratch, staged},
+    fs_pipe::commit,
+    journal::{history, record, undo},
+  };
+  use clap::Parser;
+  use std::{
+    env::set_var,
+    fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, write},
+  };
+
+  #[test]
+  fn record_and_undo() {
+    let dir = scratch(&[("a.txt", "foo\n"), ("b.txt", "foo\n")]);
+    let state = dir.join("state");
+    set_var("XDG_STATE_HOME", &state);
+    let runs = state.join(env!("CARGO_PKG_NAME"));
+    for idx in 0..70 {
+      let id = format!("0-{idx:03}");
+      let manifest = format!(
+        r#"{{"id": "{id}", "time": 0, "cwd": null, "pattern": "", "replace": "", "files": []}}"#
+      );
+      create_dir_all(runs.join(&id)).unwrap();
+      write(runs.join(&id).join("manifest.json"), manifest).unwrap();
+    }
+    let opts = parse_opts(Arguments::parse_from(["sad", "foo", "bar", "-k"])).unwrap();
+
+    block_on(async {
+      let staged = vec![
+        staged(&dir, "a.txt", "bar\n").await,
+        staged(&dir, "b.txt", "bar\n").await,
+      ];
+      assert!(record(&opts, &staged).await.is_some());
+      commit(staged).await.unwrap();
+    });
+    assert_eq!(read_dir(&runs).unwrap().count(), 64);
+
+    let listed = block_on(history()).unwrap();
+    let newest = listed[0].to_string_lossy().into_owned();
+    assert!(newest.contains(r#""foo" -> "bar""#), "{newest}");
+    assert!(!newest.contains("(undone)"), "{newest}");
+
+    write(dir.join("a.txt"), "baz\n").unwrap();
+    let err = block_on(undo(None)).unwrap_err().to_string();
+    assert!(err.contains("refusing to undo"), "{err}");
+    assert_eq!(read_to_string(dir.join("b.txt")).unwrap(), "bar\n");
+
+    write(dir.join("a.txt"), "bar\n").unwrap();
+    assert_eq!(block_on(undo(None)).unwrap().len(), 2);
+    assert_eq!(read_to_string(dir.join("a.txt")).unwrap(), "foo\n");
+    assert_eq!(read_to_string(dir.join("b.txt")).unwrap(), "foo\n");
+
+    let listed = block_on(history()).unwrap();
+    assert!(listed[0].to_string_lossy().contains("(undone)"));
+    assert!(block_on(undo(Some("../../etc"))).is_err());
+    remove_dir_all(dir).unwrap();
+  }
+}
@@ -6,25 +6,47 @@
 )]
 
 mod argparse;
+mod argparse_spec;
 mod displace;
+mod displace_spec;
+#[cfg(test)]
+mod fixture;
 mod fs_pipe;
+mod fs_pipe_spec;
 mod fzf;
 mod input;
+mod input_spec;
+#[cfg(target_family = "unix")]
+mod ipc;
+#[cfg(target_family = "unix")]
+mod ipc_spec;
+mod journal;
+mod journal_spec;
+mod prompt;
+mod prompt_spec;
+mod script;
+mod script_spec;
 mod subprocess;
+#[cfg(target_family = "unix")]
+mod subprocess_spec;
+mod template;
+mod template_spec;
+mod tui;
 mod types;
 mod udiff;
 mod udiff_spec;
 
 use {
   ansi_term::Colour,
-  argparse::{parse_args, parse_opts, Action, Options, Printer},
-  displace::displace,
+  argparse::{parse_args, parse_opts, Action, Engine, Mode, Options, Printer},
+  displace::{apply, displace, transact, Displaced},
   futures::{
-    future::{ready, Either},
-    stream::{once, select, Stream, StreamExt, TryStreamExt},
+    future::{self, ready, Either, Future},
+    stream::{iter, once, select, Stream, StreamExt, TryStreamExt},
   },
-  fzf::stream_fzf_proc,
-  input::stream_in,
+  input::{stream_in, RowIn},
+  journal::{history, undo},
+  prompt::stream_prompt,
   std::{
     convert::Into,
     ffi::OsString,
@@ -36,34 +58,47 @@ use {
   },
   subprocess::{stream_into, stream_subproc},
   tokio::{io, runtime::Builder, signal::ctrl_c},
+  tui::stream_pick,
   types::Die,
 };
+#[cfg(target_family = "unix")]
+use {
+  argparse::Live,
+  fzf::stream_fzf_proc,
+  ipc::{bind, Request, Response, Session},
+  std::{os::unix::ffi::OsStringExt, path::Path, sync::Arc},
+  tokio::fs::read,
+};
 
 fn stream_sink(
-  opts: &Options,
+  printer: &Printer,
   stream: impl Stream<Item = Result<OsString, Die>> + Unpin,
 ) -> impl Stream<Item = Result<(), Die>> {
-  match (&opts.action, &opts.printer) {
-    (Action::FzfPreview(fzf_p, fzf_a), _) => Either::Left(Either::Left(stream_fzf_proc(
-      fzf_p.clone(),
-      fzf_a.clone(),
-      stream,
-    ))),
-    (_, Printer::Pager(cmd)) => Either::Left(Either::Right(stream_subproc(cmd.clone(), stream))),
-    (_, Printer::Stdout) => {
+  match printer {
+    Printer::Pager(cmd) => Either::Left(stream_subproc(cmd.clone(), stream)),
+    Printer::Stdout => {
       let stdout = io::stdout();
       Either::Right(stream_into(PathBuf::from("/dev/stdout"), stdout, stream))
     }
   }
 }
 
+async fn sigint() -> Die {
+  match ctrl_c().await {
+    Err(e) => Die::IO(PathBuf::from("sigint"), e.kind()),
+    Ok(()) => Die::Interrupt,
+  }
+}
+
+async fn interruptible<T>(fut: impl Future<Output = Result<T, Die>>) -> Result<T, Die> {
+  match future::select(pin!(fut), pin!(sigint())).await {
+    Either::Left((ret, _)) => ret,
+    Either::Right((e, _)) => Err(e),
+  }
+}
+
 async fn consume(stream: impl Stream<Item = Result<(), Die>> + Send) -> Result<(), Die> {
-  let int = once(async {
-    match ctrl_c().await {
-      Err(e) => Die::IO(PathBuf::from("sigint"), e.kind()),
-      Ok(()) => Die::Interrupt,
-    }
-  });
+  let int = once(sigint());
   let out = select(
     stream
       .filter_map(|row| ready(row.err()))
@@ -77,27 +112,171 @@ async fn consume(stream: impl Stream<Item = Result<(), Die>> + Send) -> Result<(
   }
 }
 
+fn stream_stdout(lines: Vec<OsString>) -> impl Stream<Item = Result<(), Die>> {
+  let stdout = io::stdout();
+  stream_into(
+    PathBuf::from("/dev/stdout"),
+    stdout,
+    iter(lines.into_iter().map(Ok)),
+  )
+}
+
+async fn apply_rows(opts: &Options, rows: Vec<RowIn>) -> Result<(), Die> {
+  let names = apply(opts, rows).await?;
+  consume(stream_sink(&opts.printer, iter(names.into_iter().map(Ok)))).await
+}
+
+#[cfg(target_family = "unix")]
+async fn client(socket: PathBuf, request: Request) -> Result<(), Die> {
+  match ipc::request(&socket, &request).await? {
+    Response::Fail(e) => Err(Die::Remote(e)),
+    Response::Print(pager, print) => {
+      let printer = pager.map_or(Printer::Stdout, Printer::Pager);
+      let stream = once(ready(Ok(OsString::from_vec(print))));
+      consume(stream_sink(&printer, stream)).await
+    }
+  }
+}
+
+#[cfg(target_family = "unix")]
+async fn fzf(
+  opts: &Options,
+  fzf_p: &Path,
+  fzf_a: &[String],
+  session: Arc<Session>,
+  listed: impl Stream<Item = Result<OsString, Die>> + Unpin + Send,
+) -> Result<(), Die> {
+  let server = bind()?;
+  let live = opts
+    .live
+    .zip(opts.exprs.first())
+    .map(|(live, (pattern, replace))| match live {
+      Live::Pattern => (live, pattern.as_str()),
+      Live::Replace => (live, replace.as_str()),
+    });
+  let out_stream = stream_fzf_proc(
+    fzf_p.to_owned(),
+    fzf_a.to_owned(),
+    live,
+    &server.socket(),
+    listed,
+  );
+  let ret = match future::select(pin!(consume(out_stream)), pin!(server.serve(session))).await {
+    Either::Left((ret, _)) => ret,
+    Either::Right((e, _)) => Err(e),
+  };
+  ret
+}
+
+fn report_unmapped(opts: &Options) {
+  for (path, keys) in opts.engines.iter().flat_map(Engine::unmapped) {
+    let msg = format!("Unmapped in {} :: {}", path.display(), keys.join(", "));
+    eprintln!("{}", Colour::Yellow.paint(msg));
+  }
+}
+
 async fn run(threads: usize) -> Result<(), Die> {
-  let (mode, args) = parse_args();
-  let input_stream = stream_in(&mode, &args).await;
-  let opts = parse_opts(mode, args)?;
+  #[cfg(target_family = "unix")]
+  let args = {
+    let selection =
+      |path: PathBuf| async move { read(&path).await.map_err(|e| Die::IO(path, e.kind())) };
+    match parse_args() {
+      Mode::Initial(args) => args,
+      Mode::Preview(socket, path) => {
+        return client(socket, Request::Preview(selection(path).await?)).await
+      }
+      Mode::Patch(socket, path) => {
+        return client(socket, Request::Patch(selection(path).await?)).await
+      }
+      Mode::Reload(socket, query) => {
+        return client(socket, Request::Reload(query.into_vec())).await
+      }
+    }
+  };
+  #[cfg(target_family = "windows")]
+  let Mode::Initial(args) = parse_args() else {
+    return Err(Die::ArgumentError(
+      "fzf mode requires unix sockets".to_owned(),
+    ));
+  };
 
-  let trans_stream = input_stream
-    .map_ok(|input| displace(&opts, input))
-    .try_buffer_unordered(threads);
-  let out_stream = stream_sink(&opts, trans_stream.boxed());
+  if args.history {
+    return consume(stream_stdout(history().await?)).await;
+  }
+  if let Some(run_id) = &args.undo {
+    let names = undo(run_id.as_deref()).await?;
+    return consume(stream_stdout(names)).await;
+  }
+  let input_stream = stream_in(&args);
+  #[cfg(target_family = "unix")]
+  let opts = Arc::new(parse_opts(args)?);
+  #[cfg(target_family = "windows")]
+  let opts = parse_opts(args)?;
+  #[cfg(target_family = "unix")]
+  let session = Arc::new(Session::new(opts.clone(), threads));
+  #[cfg(target_family = "unix")]
+  let input_stream = {
+    let session = session.clone();
+    input_stream.inspect_ok(move |row| session.track(row.path()))
+  };
+
+  let trans_stream = input_stream.map_ok(|input| displace(&opts, input));
+  let trans_stream = match opts.action {
+    Action::Interactive => Either::Left(trans_stream.try_buffered(opts.lanes(threads))),
+    _ => Either::Right(trans_stream.try_buffer_unordered(opts.lanes(threads))),
+  };
 
-  consume(out_stream).await
+  let ret = match &opts.action {
+    Action::Commit => {
+      let displaced = interruptible(trans_stream.try_collect::<Vec<_>>()).await?;
+      let names = transact(&opts, displaced).await?;
+      consume(stream_sink(&opts.printer, iter(names.into_iter().map(Ok)))).await
+    }
+    #[cfg(target_family = "unix")]
+    Action::FzfPreview(fzf_p, fzf_a) => {
+      let listed = {
+        let session = session.clone();
+        trans_stream.map_ok(move |displaced| match displaced {
+          Displaced::Listed(print, diffed) => {
+            session.insert(diffed);
+            print
+          }
+          displaced => displaced.into_print(),
+        })
+      };
+      fzf(&opts, fzf_p, fzf_a, session, listed.boxed()).await
+    }
+    Action::Interactive => {
+      let rows = interruptible(stream_prompt(opts.unified, trans_stream)).await?;
+      apply_rows(&opts, rows).await
+    }
+    Action::Tui => {
+      let rows = interruptible(stream_pick(opts.unified, trans_stream)).await?;
+      apply_rows(&opts, rows).await
+    }
+    _ => {
+      let out_stream = stream_sink(
+        &opts.printer,
+        trans_stream.map_ok(Displaced::into_print).boxed(),
+      );
+      consume(out_stream).await
+    }
+  };
+  report_unmapped(&opts);
+  ret
 }
 
 fn main() -> impl Termination {
-  let threads = available_parallelism().map(Into::into).unwrap_or(6);
+  let threads = available_parallelism().map_or(6, Into::into);
   let rt = Builder::new_multi_thread()
     .enable_io()
     .build()
     .expect("runtime failure");
 
-  match rt.block_on(run(threads)).err() {
+  let ret = rt.block_on(run(threads));
+  rt.shutdown_background();
+
+  match ret.err() {
     None => ExitCode::SUCCESS,
     Some(Die::Interrupt) => ExitCode::from(130),
     Some(e) => {
@@ -0,0 +1,229 @@
+use {
+  super::{
+    displace::{Diffed, Displaced},
+    input::RowIn,
+    types::Die,
+    udiff::{digest, p_edit, DiffRange},
+  },
+  ansi_term::Colour,
+  futures::stream::{Stream, TryStreamExt},
+  shlex::split,
+  std::{
+    collections::{HashMap, HashSet},
+    env::{temp_dir, var},
+    fs::{read_to_string, remove_file, write, File, OpenOptions},
+    io::{stderr, BufRead, BufReader, Write},
+    path::PathBuf,
+    pin::pin,
+    process::Command,
+  },
+  tokio::task::spawn_blocking,
+  uuid::Uuid,
+};
+
+#[cfg(target_family = "unix")]
+const TTY: &str = "/dev/tty";
+#[cfg(target_family = "windows")]
+const TTY: &str = "CONIN$";
+
+const HELP: &str = "y - apply this hunk
+n - skip this hunk
+a - apply this hunk and all later hunks in the file
+d - skip this hunk and all later hunks in the file
+q - quit, skip this hunk and all remaining ones, same as EOF
+e - manually edit this hunk in $EDITOR
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Answer {
+  Yes,
+  No,
+  All,
+  Done,
+  Quit,
+  Edit,
+}
+
+fn colourize(print: &str) -> String {
+  print
+    .split_inclusive('\n')
+    .map(|line| {
+      let (body, nl) = line
+        .strip_suffix('\n')
+        .map_or((line, ""), |body| (body, "\n"));
+      let painted = match body.chars().next() {
+        _ if body.starts_with("+++") || body.starts_with("---") => {
+          Colour::White.bold().paint(body).to_string()
+        }
+        Some('+') => Colour::Green.paint(body).to_string(),
+        Some('-') => Colour::Red.paint(body).to_string(),
+        Some('@') => Colour::Cyan.paint(body).to_string(),
+        _ => body.to_owned(),
+      };
+      painted + nl
+    })
+    .collect()
+}
+
+pub fn ask(tty: &mut impl BufRead, hunk: &str, idx: usize, total: usize) -> Result<Answer, Die> {
+  let io_err = |e: std::io::Error| Die::IO(PathBuf::from(TTY), e.kind());
+  let mut err = stderr().lock();
+  write!(err, "{hunk}").map_err(io_err)?;
+  loop {
+    write!(
+      err,
+      "{}",
+      Colour::Blue
+        .bold()
+        .paint(format!("({idx}/{total}) Apply this hunk [y,n,a,d,q,e,?]? "))
+    )
+    .map_err(io_err)?;
+    err.flush().map_err(io_err)?;
+
+    let mut line = String::new();
+    if tty.read_line(&mut line).map_err(io_err)? == 0 {
+      writeln!(err).map_err(io_err)?;
+      return Ok(Answer::Quit);
+    }
+    match line.trim() {
+      "y" => return Ok(Answer::Yes),
+      "n" => return Ok(Answer::No),
+      "a" => return Ok(Answer::All),
+      "d" => return Ok(Answer::Done),
+      "q" => return Ok(Answer::Quit),
+      "e" => return Ok(Answer::Edit),
+      _ => write!(err, "{}", Colour::Red.paint(HELP)).map_err(io_err)?,
+    }
+  }
+}
+
+const EDIT_GUIDE: &str = "# ---
+# To skip '-' lines, make them ' ' lines (context).
+# To skip '+' lines, delete them.
+# Lines starting with # will be removed.
+# Context and '-' lines must still match the file, otherwise the edit is rejected.
+";
+
+fn edit(hunk: &str, range: &DiffRange, before: &[String]) -> Result<Option<Vec<String>>, Die> {
+  let uuid = Uuid::new_v4().as_simple().to_string();
+  let path = temp_dir().join(format!("{}-{uuid}.diff", env!("CARGO_PKG_NAME")));
+  let io_err = |e: std::io::Error| Die::IO(path.clone(), e.kind());
+  write(&path, format!("{hunk}{EDIT_GUIDE}")).map_err(io_err)?;
+
+  let editor = var("VISUAL")
+    .or_else(|_| var("EDITOR"))
+    .ok()
+    .and_then(|editor| split(&editor))
+    .filter(|argv| !argv.is_empty())
+    .unwrap_or_else(|| vec!["vi".to_owned()]);
+  let tty = OpenOptions::new()
+    .read(true)
+    .write(true)
+    .open(TTY)
+    .and_then(|tty| Ok((tty.try_clone()?, tty)))
+    .map_err(|e| Die::IO(PathBuf::from(TTY), e.kind()));
+  let status = tty.and_then(|(stdin, stdout)| {
+    Command::new(&editor[0])
+      .args(&editor[1..])
+      .arg(&path)
+      .stdin(stdin)
+      .stdout(stdout)
+      .status()
+      .map_err(|e| Die::IO(PathBuf::from(&editor[0]), e.kind()))
+  });
+  let edited = read_to_string(&path).map_err(io_err);
+  let _ = remove_file(&path);
+
+  match status?.code() {
+    Some(0) => Ok(p_edit(&edited?, range, before)),
+    code => Err(Die::BadExit(PathBuf::from(&editor[0]), code.unwrap_or(1))),
+  }
+}
+
+fn prompt(unified: usize, diffed: &Diffed) -> Result<(Option<RowIn>, bool), Die> {
+  let file = File::open(TTY).map_err(|e| Die::IO(PathBuf::from(TTY), e.kind()))?;
+  let mut tty = BufReader::new(file);
+
+  let hunks = diffed.units(unified);
+  let total = hunks.len();
+  let mut picks = HashMap::new();
+  let mut edits = HashMap::new();
+  let mut answer = None;
+  let mut quit = false;
+
+  for (idx, range) in hunks.into_iter().enumerate() {
+    let one = HashSet::from([range.clone()]);
+    let print = diffed
+      .render(unified, Some(&one))
+      .to_string_lossy()
+      .into_owned();
+    let ans = loop {
+      let ans = match answer {
+        Some(ans) => ans,
+        None => ask(&mut tty, &colourize(&print), idx + 1, total)?,
+      };
+      if !matches!(ans, Answer::Edit) {
+        break ans;
+      }
+      if diffed.edits.is_some() {
+        eprintln!("{}", Colour::Red.paint("Single matches cannot be edited"));
+        continue;
+      }
+      match edit(&print, &range, &diffed.before) {
+        Ok(Some(lines)) => {
+          edits.insert(digest(&range, &diffed.before), lines);
+          break Answer::Yes;
+        }
+        Ok(None) => eprintln!(
+          "{}",
+          Colour::Red.paint("Edited hunk does not match the file, try again")
+        ),
+        Err(e) => eprintln!("{}", Colour::Red.paint(format!("{e}, try again"))),
+      }
+    };
+    if matches!(ans, Answer::All | Answer::Done | Answer::Quit) {
+      answer = Some(ans);
+    }
+    match ans {
+      Answer::Yes | Answer::All => {
+        let digest = digest(&range, &diffed.before);
+        picks.insert(range, digest);
+      }
+      Answer::No | Answer::Done | Answer::Edit => (),
+      Answer::Quit => {
+        quit = true;
+        break;
+      }
+    }
+  }
+
+  let row = if picks.is_empty() {
+    None
+  } else {
+    Some(RowIn::Piecewise(diffed.path.clone(), picks, edits))
+  };
+  Ok((row, quit))
+}
+
+pub async fn stream_prompt(
+  unified: usize,
+  stream: impl Stream<Item = Result<Displaced, Die>>,
+) -> Result<Vec<RowIn>, Die> {
+  let mut stream = pin!(stream);
+  let mut rows = Vec::new();
+
+  while let Some(displaced) = stream.try_next().await? {
+    let Displaced::Listed(_, diffed) = displaced else {
+      continue;
+    };
+    let (row, quit) = spawn_blocking(move || prompt(unified, &diffed))
+      .await
+      .map_err(|_| Die::Interrupt)??;
+    rows.extend(row);
+    if quit {
+      break;
+    }
+  }
+
+  Ok(rows)
+}
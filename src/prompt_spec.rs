@@ -0,0 +1,13 @@
+#[cfg(test)]
+mod spec {
+  use super::super::prompt::{ask, Answer};
+  use std::io::Cursor;
+
+  #[test]
+  fn answers() {
+    let mut tty = Cursor::new("?\ny\nn\n");
+    assert_eq!(ask(&mut tty, "", 1, 3).unwrap(), Answer::Yes);
+    assert_eq!(ask(&mut tty, "", 2, 3).unwrap(), Answer::No);
+    assert_eq!(ask(&mut tty, "", 3, 3).unwrap(), Answer::Quit);
+  }
+}
@@ -0,0 +1,79 @@
+use {
+  super::{template::Scope, types::Die},
+  regex::{Captures, Regex},
+  rhai::{CallFnOptions, Dynamic, Engine, Map, Scope as Env, AST},
+  std::path::Path,
+};
+
+const ENTRY: &str = "replace";
+
+#[derive(Debug)]
+pub struct Script {
+  engine: Engine,
+  ast: AST,
+}
+
+pub fn p_script(path: &Path) -> Result<Script, Die> {
+  let mut engine = Engine::new();
+  engine
+    .set_max_operations(1_000_000)
+    .set_max_call_levels(64)
+    .set_max_expr_depths(64, 32)
+    .set_max_string_size(1 << 24)
+    .set_max_array_size(1 << 16)
+    .set_max_map_size(1 << 16)
+    .on_print(|text| eprintln!("{text}"))
+    .on_debug(|text, _, pos| eprintln!("{pos} :: {text}"));
+  let die = |e| Die::ScriptError(format!("{} :: {e}", path.display()));
+  let ast = engine.compile_file(path.to_owned()).map_err(die)?;
+  if !ast
+    .iter_functions()
+    .any(|f| f.name == ENTRY && f.params.len() == 3)
+  {
+    return Err(Die::ScriptError(format!(
+      "{} :: expected fn {ENTRY}(groups, file, line)",
+      path.display()
+    )));
+  }
+  engine.run_ast(&ast).map_err(die)?;
+  Ok(Script { engine, ast })
+}
+
+impl Script {
+  /// `()` from the script leaves the match as is
+  pub fn replace(
+    &self,
+    re: &Regex,
+    captures: &Captures,
+    scope: &Scope,
+  ) -> Result<Option<String>, Die> {
+    let mut groups = Map::new();
+    for (idx, name) in re.capture_names().enumerate() {
+      let group = captures
+        .get(idx)
+        .map_or(Dynamic::UNIT, |m| m.as_str().into());
+      if let Some(name) = name {
+        groups.insert(name.into(), group.clone());
+      }
+      groups.insert(idx.to_string().into(), group);
+    }
+    let file = scope.name.to_string_lossy().into_owned();
+    let line = i64::try_from(scope.line).unwrap_or(i64::MAX);
+
+    let replacement = self
+      .engine
+      .call_fn_with_options::<Dynamic>(
+        CallFnOptions::new().eval_ast(false),
+        &mut Env::new(),
+        &self.ast,
+        ENTRY,
+        (groups, file, line),
+      )
+      .map_err(|e| Die::ScriptError(format!("{} :: {e}", scope.name.display())))?;
+    Ok(if replacement.is_unit() {
+      None
+    } else {
+      Some(replacement.to_string())
+    })
+  }
+}
@@ -0,0 +1,40 @@
+#[cfg(test)]
+mod spec {
+  use super::super::{fixture::scratch, script::p_script, template::Scope};
+  use {
+    regex::Regex,
+    std::{fs::remove_dir_all, path::Path},
+  };
+
+  #[test]
+  fn script() {
+    let dir = scratch(&[
+      (
+        "upper.rhai",
+        "fn replace(groups, file, line) { if groups.w == \"keep\" { () } else { `${groups[\"0\"].to_upper()}@${line}` } }",
+      ),
+      ("nameless.rhai", "fn rewrite(groups, file, line) { \"x\" }"),
+      ("spin.rhai", "fn replace(groups, file, line) { loop {} }"),
+    ]);
+    let re = Regex::new(r"(?<w>\w+)").unwrap();
+    let scope = Scope {
+      name: Path::new("a.txt"),
+      count: 0,
+      total: 0,
+      line: 2,
+    };
+    let call = |name: &str, text: &str| {
+      let script = p_script(&dir.join(name))?;
+      script.replace(&re, &re.captures(text).unwrap(), &scope)
+    };
+
+    assert_eq!(call("upper.rhai", "foo").unwrap().as_deref(), Some("FOO@2"));
+    assert_eq!(call("upper.rhai", "keep").unwrap(), None);
+
+    let err = call("nameless.rhai", "foo").unwrap_err().to_string();
+    assert!(err.contains("expected fn replace"), "{err}");
+    let err = call("spin.rhai", "foo").unwrap_err().to_string();
+    assert!(err.contains("operations"), "{err}");
+    remove_dir_all(dir).unwrap();
+  }
+}
@@ -4,14 +4,28 @@ use {
     future::{ready, Either},
     stream::{once, select, try_unfold, Stream, StreamExt},
   },
-  std::{collections::HashMap, ffi::OsString, marker::Unpin, path::PathBuf, process::Stdio},
+  serde::{Deserialize, Serialize},
+  std::{
+    collections::HashMap,
+    ffi::OsString,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    marker::Unpin,
+    path::PathBuf,
+    process::{Child, ChildStdin, Command as StdCommand, Stdio},
+    sync::{
+      mpsc::{channel, Receiver, RecvTimeoutError},
+      Mutex,
+    },
+    thread::spawn,
+    time::Duration,
+  },
   tokio::{
     io::{AsyncWrite, AsyncWriteExt, BufWriter},
     process::Command,
   },
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct SubprocCommand {
   pub prog: PathBuf,
   pub args: Vec<String>,
@@ -29,6 +43,10 @@ where
   try_unfold((stream, buf, path), |mut s| async {
     match s.0.next().await {
       None => {
+        s.1
+          .flush()
+          .await
+          .map_err(|e| Die::IO(s.2.clone(), e.kind()))?;
         s.1
           .shutdown()
           .await
@@ -92,3 +110,98 @@ pub fn stream_subproc(
     }
   }
 }
+
+/// How long `--replace-cmd` may take to answer a match
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+struct Pipe {
+  child: Child,
+  stdin: ChildStdin,
+  replies: Receiver<io::Result<String>>,
+}
+
+/// Long-lived child for `--replace-cmd`, one JSON line in, one JSON line out, per match
+///
+/// Calls block, run them off the async workers, ie. `spawn_blocking`
+#[derive(Debug)]
+pub struct Coprocess {
+  cmd: SubprocCommand,
+  timeout: Duration,
+  pipe: Mutex<Option<Pipe>>,
+}
+
+impl Coprocess {
+  pub const fn new(cmd: SubprocCommand, timeout: Duration) -> Self {
+    Self {
+      cmd,
+      timeout,
+      pipe: Mutex::new(None),
+    }
+  }
+
+  fn spawn(&self) -> Result<Pipe, Die> {
+    let mut child = StdCommand::new(&self.cmd.prog)
+      .args(&self.cmd.args)
+      .envs(&self.cmd.env)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .spawn()
+      .map_err(|e| Die::IO(self.cmd.prog.clone(), e.kind()))?;
+    let stdin = child.stdin.take().expect("child process stdin");
+    let stdout = child.stdout.take().expect("child process stdout");
+    let (tx, replies) = channel();
+    spawn(move || {
+      for line in BufReader::new(stdout).lines() {
+        if tx.send(line).is_err() {
+          break;
+        }
+      }
+    });
+    Ok(Pipe {
+      child,
+      stdin,
+      replies,
+    })
+  }
+
+  /// `None` when the child answers `null`, ie. leave the match as is
+  pub fn call(&self, text: &str) -> Result<Option<String>, Die> {
+    let die = |kind| Die::IO(self.cmd.prog.clone(), kind);
+    let mut pipe = self.pipe.lock().expect("coprocess lock");
+    if pipe.is_none() {
+      *pipe = Some(self.spawn()?);
+    }
+    let Pipe { stdin, replies, .. } = pipe.as_mut().expect("coprocess spawned");
+
+    let mut line = serde_json::to_string(text).expect("json string");
+    line.push('\n');
+    stdin
+      .write_all(line.as_bytes())
+      .and_then(|()| stdin.flush())
+      .map_err(|e| die(e.kind()))?;
+
+    let reply = match replies.recv_timeout(self.timeout) {
+      Ok(reply) => reply.map_err(|e| die(e.kind())),
+      Err(RecvTimeoutError::Timeout) => {
+        if let Some(Pipe { mut child, .. }) = pipe.take() {
+          let _ = child.kill();
+          let _ = child.wait();
+        }
+        Err(die(ErrorKind::TimedOut))
+      }
+      Err(RecvTimeoutError::Disconnected) => Err(die(ErrorKind::UnexpectedEof)),
+    };
+    drop(pipe);
+    serde_json::from_str(&reply?).map_err(|_| die(ErrorKind::InvalidData))
+  }
+}
+
+impl Drop for Coprocess {
+  fn drop(&mut self) {
+    if let Some(Pipe { mut child, .. }) = self.pipe.get_mut().ok().and_then(Option::take) {
+      let _ = child.kill();
+      let _ = child.wait();
+    }
+  }
+}
@@ -0,0 +1,49 @@
+#[cfg(test)]
+mod spec {
+  use super::super::{
+    subprocess::{Coprocess, SubprocCommand, REPLY_TIMEOUT},
+    types::Die,
+  };
+  use std::{collections::HashMap, io::ErrorKind, path::PathBuf, time::Duration};
+
+  fn sh(script: &str, timeout: Duration) -> Coprocess {
+    let cmd = SubprocCommand {
+      prog: PathBuf::from("sh"),
+      args: vec!["-c".to_owned(), script.to_owned()],
+      env: HashMap::new(),
+    };
+    Coprocess::new(cmd, timeout)
+  }
+
+  fn kind(result: Result<Option<String>, Die>) -> ErrorKind {
+    match result {
+      Err(Die::IO(_, kind)) => kind,
+      other => panic!("expected IO error, got {other:?}"),
+    }
+  }
+
+  #[test]
+  fn coprocess() {
+    let echo = sh(
+      r#"while read -r line; do
+        case "$line" in '"keep"') echo null ;; *) printf '%s\n' "$line" | sed 's/o/0/g' ;; esac
+      done"#,
+      REPLY_TIMEOUT,
+    );
+    assert_eq!(
+      echo.call("foo\n\"bar\"").unwrap().as_deref(),
+      Some("f00\n\"bar\"")
+    );
+    assert_eq!(echo.call("keep").unwrap(), None);
+    assert_eq!(echo.call("boo").unwrap().as_deref(), Some("b00"));
+
+    let mute = sh("while read -r line; do :; done", Duration::from_millis(100));
+    assert_eq!(kind(mute.call("foo")), ErrorKind::TimedOut);
+
+    let dies = sh("read -r line", REPLY_TIMEOUT);
+    assert_eq!(kind(dies.call("foo")), ErrorKind::UnexpectedEof);
+
+    let garbled = sh("while read -r line; do echo nope; done", REPLY_TIMEOUT);
+    assert_eq!(kind(garbled.call("foo")), ErrorKind::InvalidData);
+  }
+}
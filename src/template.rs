@@ -0,0 +1,381 @@
+use {
+  super::{argparse::p_dictionary, types::Die},
+  std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+    env::var,
+    ffi::OsStr,
+    iter::Peekable,
+    mem::take,
+    path::{Path, PathBuf},
+    str::CharIndices,
+    sync::{Arc, Mutex},
+  },
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Group {
+  Index(usize),
+  Name(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Case {
+  Upper,
+  Lower,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Operand {
+  Capture(Group),
+  Counter(i64, i64),
+  Total(i64, i64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Number {
+  operand: Operand,
+  ops: Vec<(char, i64)>,
+  zero: bool,
+  width: usize,
+}
+
+/// Lookup table for `${map:FILE:$1}`, keys not found are collected for the summary
+#[derive(Debug)]
+pub struct Table {
+  path: PathBuf,
+  entries: HashMap<String, String>,
+  misses: Mutex<BTreeSet<String>>,
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+  Literal(String),
+  Capture(Group),
+  Number(Number),
+  Lookup(Arc<Table>, Group),
+  Span(Option<Case>),
+  Next(Case),
+}
+
+/// Where a match sits, for `${#}` `${##}` `${FILE}` `${STEM}` `${EXT}` `${LINE}`
+pub struct Scope<'a> {
+  pub name: &'a Path,
+  pub count: usize,
+  pub total: usize,
+  pub line: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Template {
+  parts: Vec<Part>,
+}
+
+pub fn p_group(name: &str) -> Group {
+  name
+    .parse::<usize>()
+    .map_or_else(|_| Group::Name(name.to_owned()), Group::Index)
+}
+
+fn p_int(text: &str) -> (Option<i64>, &str) {
+  let end = text
+    .char_indices()
+    .find(|&(idx, c)| !(c.is_ascii_digit() || (idx == 0 && c == '-')))
+    .map_or(text.len(), |(idx, _)| idx);
+  (text[..end].parse().ok(), &text[end..])
+}
+
+fn p_number(body: &str) -> Option<Number> {
+  let (expr, spec) = body.split_once(':').unwrap_or((body, ""));
+  let zero = spec.starts_with('0');
+  let width = if spec.is_empty() {
+    0
+  } else {
+    spec.parse().ok()?
+  };
+
+  let (operand, mut rest) = if let Some(rest) = expr.strip_prefix('#') {
+    let (total, rest) = rest
+      .strip_prefix('#')
+      .map_or((false, rest), |rest| (true, rest));
+    let (start, rest) = p_int(rest);
+    let (step, rest) = match rest.strip_prefix(',') {
+      Some(rest) => match p_int(rest) {
+        (Some(step), rest) => (step, rest),
+        (None, _) => return None,
+      },
+      None => (1, rest),
+    };
+    let start = start.unwrap_or(1);
+    if total {
+      (Operand::Total(start, step), rest)
+    } else {
+      (Operand::Counter(start, step), rest)
+    }
+  } else {
+    let end = expr
+      .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+      .unwrap_or(expr.len());
+    if end == 0 {
+      return None;
+    }
+    (Operand::Capture(p_group(&expr[..end])), &expr[end..])
+  };
+
+  let mut ops = Vec::new();
+  while let Some(op) = rest.chars().next().filter(|c| "+-*/%".contains(*c)) {
+    match p_int(&rest[1..]) {
+      (Some(n), tail) => {
+        ops.push((op, n));
+        rest = tail;
+      }
+      _ => return None,
+    }
+  }
+  if !rest.is_empty()
+    || (ops.is_empty() && spec.is_empty() && matches!(operand, Operand::Capture(_)))
+  {
+    return None;
+  }
+  Some(Number {
+    operand,
+    ops,
+    zero,
+    width,
+  })
+}
+
+/// Counters, or a group followed by an operator or a format spec
+fn p_arithmetic(body: &str) -> bool {
+  let end = body
+    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+    .unwrap_or(body.len());
+  body.starts_with('#') || (end > 0 && body[end..].starts_with(['+', '-', '*', '/', '%', ':']))
+}
+
+fn p_lookup(spec: &str) -> Result<Part, Die> {
+  let Some((path, key)) = spec.rsplit_once(':') else {
+    return Err(Die::ArgumentError(format!(
+      "expected ${{map:FILE:$GROUP}} :: {spec}"
+    )));
+  };
+  let key = key.strip_prefix('$').unwrap_or(key);
+  let path = PathBuf::from(path);
+  let table = Table {
+    entries: p_dictionary(&path)?.into_iter().collect(),
+    misses: Mutex::new(BTreeSet::new()),
+    path,
+  };
+  Ok(Part::Lookup(Arc::new(table), p_group(key)))
+}
+
+fn skip(chars: &mut Peekable<CharIndices>, stop: usize) {
+  while chars.next_if(|(idx, _)| *idx < stop).is_some() {}
+}
+
+pub fn p_template(replace: &str) -> Result<Template, Die> {
+  let mut parts = Vec::new();
+  let mut literal = String::new();
+  let mut chars = replace.char_indices().peekable();
+
+  while let Some((idx, c)) = chars.next() {
+    let part = match (c, chars.peek().map(|(_, c)| *c)) {
+      ('\\', Some('U')) => Some(Part::Span(Some(Case::Upper))),
+      ('\\', Some('L')) => Some(Part::Span(Some(Case::Lower))),
+      ('\\', Some('E')) => Some(Part::Span(None)),
+      ('\\', Some('u')) => Some(Part::Next(Case::Upper)),
+      ('\\', Some('l')) => Some(Part::Next(Case::Lower)),
+      ('\\', Some('\\')) if replace[idx + 2..].starts_with(['U', 'L', 'E', 'u', 'l']) => {
+        chars.next();
+        literal.push('\\');
+        continue;
+      }
+      ('$', Some('$')) => {
+        chars.next();
+        literal.push('$');
+        continue;
+      }
+      ('$', Some('{')) => {
+        let rest = &replace[idx + 2..];
+        match rest.find('}') {
+          Some(end) if end > 0 => {
+            skip(&mut chars, idx + 2 + end + 1);
+            let body = &rest[..end];
+            if let Some(spec) = body.strip_prefix("map:") {
+              Some(p_lookup(spec)?)
+            } else if let Some(key) = body.strip_prefix("ENV:") {
+              Some(Part::Literal(var(key).unwrap_or_default()))
+            } else if p_arithmetic(body) {
+              let number = p_number(body)
+                .ok_or_else(|| Die::ArgumentError(format!("Invalid arithmetic :: ${{{body}}}")))?;
+              Some(Part::Number(number))
+            } else {
+              Some(Part::Capture(p_group(body)))
+            }
+          }
+          _ => None,
+        }
+      }
+      ('$', Some(_)) => {
+        let rest = &replace[idx + 1..];
+        let end = rest
+          .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+          .unwrap_or(rest.len());
+        if end == 0 {
+          None
+        } else {
+          skip(&mut chars, idx + 1 + end);
+          Some(Part::Capture(p_group(&rest[..end])))
+        }
+      }
+      _ => None,
+    };
+    match part {
+      Some(part) => {
+        if matches!(part, Part::Span(_) | Part::Next(_)) {
+          chars.next();
+        }
+        if !literal.is_empty() {
+          parts.push(Part::Literal(take(&mut literal)));
+        }
+        parts.push(part);
+      }
+      None => literal.push(c),
+    }
+  }
+  if !literal.is_empty() {
+    parts.push(Part::Literal(literal));
+  }
+  Ok(Template { parts })
+}
+
+fn push(out: &mut String, text: &str, span: Option<Case>, next: &mut Option<Case>) {
+  for c in text.chars() {
+    match next.take().or(span) {
+      Some(Case::Upper) => out.extend(c.to_uppercase()),
+      Some(Case::Lower) => out.extend(c.to_lowercase()),
+      None => out.push(c),
+    }
+  }
+}
+
+fn resolve<'a>(
+  scope: &Scope,
+  lookup: &impl Fn(&Group) -> Option<&'a str>,
+  group: &Group,
+) -> Option<Cow<'a, str>> {
+  let lossy = |os: Option<&OsStr>| os.map(|os| os.to_string_lossy().into_owned());
+  lookup(group).map(Cow::Borrowed).or_else(|| {
+    let Group::Name(name) = group else {
+      return None;
+    };
+    match name.as_str() {
+      "FILE" => lossy(Some(scope.name.as_os_str())),
+      "STEM" => lossy(scope.name.file_stem()),
+      "EXT" => lossy(scope.name.extension()),
+      "LINE" => Some(scope.line.to_string()),
+      _ => None,
+    }
+    .map(Cow::Owned)
+  })
+}
+
+impl Number {
+  fn eval<'a>(&self, scope: &Scope, lookup: &impl Fn(&Group) -> Option<&'a str>) -> Option<String> {
+    let value = match &self.operand {
+      Operand::Capture(group) => resolve(scope, lookup, group)?.trim().parse::<i64>().ok()?,
+      Operand::Counter(start, step) => step
+        .checked_mul(i64::try_from(scope.count).ok()?)?
+        .checked_add(*start)?,
+      Operand::Total(start, step) => step
+        .checked_mul(i64::try_from(scope.total).ok()?)?
+        .checked_add(*start)?,
+    };
+    let value = self
+      .ops
+      .iter()
+      .try_fold(value, |value, &(op, n)| match op {
+        '+' => value.checked_add(n),
+        '-' => value.checked_sub(n),
+        '*' => value.checked_mul(n),
+        '/' => value.checked_div(n),
+        _ => value.checked_rem(n),
+      })?;
+    let width = self.width;
+    Some(if self.zero {
+      format!("{value:0width$}")
+    } else {
+      format!("{value:width$}")
+    })
+  }
+}
+
+impl Template {
+  /// `None` leaves the match as is
+  pub fn expand<'a>(
+    &self,
+    scope: &Scope,
+    lookup: impl Fn(&Group) -> Option<&'a str>,
+  ) -> Option<String> {
+    let mut out = String::new();
+    let mut span = None;
+    let mut next = None;
+    for part in &self.parts {
+      match part {
+        Part::Literal(text) => push(&mut out, text, span, &mut next),
+        Part::Capture(group) => push(
+          &mut out,
+          &resolve(scope, &lookup, group).unwrap_or_default(),
+          span,
+          &mut next,
+        ),
+        Part::Number(number) => push(&mut out, &number.eval(scope, &lookup)?, span, &mut next),
+        Part::Lookup(table, group) => {
+          let key = resolve(scope, &lookup, group).unwrap_or_default();
+          let Some(value) = table.entries.get(key.as_ref()) else {
+            let mut misses = table.misses.lock().expect("table lock");
+            misses.insert(key.into_owned());
+            drop(misses);
+            return None;
+          };
+          push(&mut out, value, span, &mut next);
+        }
+        Part::Span(case) => span = *case,
+        Part::Next(case) => next = Some(*case),
+      }
+    }
+    Some(out)
+  }
+
+  /// Uses `${##}`, which counts across files
+  pub fn has_total(&self) -> bool {
+    self.parts.iter().any(|part| {
+      matches!(
+        part,
+        Part::Number(Number {
+          operand: Operand::Total(..),
+          ..
+        })
+      )
+    })
+  }
+
+  /// Keys `${map:..}` did not find, by table
+  pub fn unmapped(&self) -> Vec<(PathBuf, Vec<String>)> {
+    self
+      .parts
+      .iter()
+      .filter_map(|part| match part {
+        Part::Lookup(table, _) => {
+          let misses = table.misses.lock().expect("table lock");
+          Some((
+            table.path.clone(),
+            misses.iter().cloned().collect::<Vec<_>>(),
+          ))
+        }
+        _ => None,
+      })
+      .filter(|(_, misses)| !misses.is_empty())
+      .collect()
+  }
+}
@@ -0,0 +1,73 @@
+#[cfg(test)]
+mod spec {
+  use super::super::template::{p_template, Group, Scope};
+  use std::path::Path;
+
+  fn expand(replace: &str, groups: &[&str]) -> String {
+    p_template(replace)
+      .unwrap()
+      .expand(
+        &Scope {
+          name: Path::new("src/lib.rs"),
+          count: 2,
+          total: 40,
+          line: 7,
+        },
+        |group| match group {
+          Group::Index(idx) => groups.get(*idx).copied(),
+          Group::Name(name) if name == "name" => groups.get(1).copied(),
+          Group::Name(_) => None,
+        },
+      )
+      .unwrap_or_else(|| "<skip>".to_owned())
+  }
+
+  #[test]
+  fn captures() {
+    let groups = ["get_user", "user"];
+    assert_eq!(expand("get$1", &groups), "getuser");
+    assert_eq!(expand("${1}s $name $2 $$1 $", &groups), "users user  $1 $");
+    assert_eq!(expand("${nope}x", &groups), "x");
+  }
+
+  #[test]
+  fn case_escapes() {
+    let groups = ["get_user_id", "user_id"];
+    assert_eq!(expand("get\\u$1", &groups), "getUser_id");
+    assert_eq!(expand("\\U$1\\E!", &groups), "USER_ID!");
+    assert_eq!(expand("\\Uab\\lCd\\Eef", &groups), "ABcDef");
+    assert_eq!(expand("\\L\\u${name}X", &groups), "User_idx");
+    assert_eq!(expand("\\\\U$1", &groups), "\\Uuser_id");
+    assert_eq!(expand("C:\\\\dir\\$1", &groups), "C:\\\\dir\\user_id");
+    assert_eq!(expand("a\\nb", &groups), "a\\nb");
+  }
+
+  #[test]
+  fn arithmetic() {
+    let groups = ["v7", "7"];
+    assert_eq!(expand("${#}", &groups), "3");
+    assert_eq!(expand("${#10,5}", &groups), "20");
+    assert_eq!(expand("${#0,-1:03}", &groups), "-02");
+    assert_eq!(expand("${##}", &groups), "41");
+    assert_eq!(expand("${##100,10}", &groups), "500");
+    assert_eq!(expand("${1+10}", &groups), "17");
+    assert_eq!(expand("${1*3-1:04}", &groups), "0020");
+    assert_eq!(expand("${1+0:3}|", &groups), "  7|");
+    assert_eq!(expand("${0+1}", &groups), "<skip>");
+    assert_eq!(expand("${1/0}", &groups), "<skip>");
+    for malformed in ["${1+}", "${1+x}", "${#,}", "${1+0:x}", "${##x}"] {
+      assert!(p_template(malformed).is_err(), "{malformed}");
+    }
+  }
+
+  #[test]
+  fn placeholders() {
+    let groups = ["x"];
+    assert_eq!(
+      expand("${FILE}|${STEM}|${EXT}|${LINE}", &groups),
+      "src/lib.rs|lib|rs|7"
+    );
+    assert_eq!(expand("${LINE*10:04}", &groups), "0070");
+    assert_eq!(expand("${ENV:SAD_TEMPLATE_SPEC_UNSET}.", &groups), ".");
+  }
+}
@@ -0,0 +1,359 @@
+use {
+  super::{
+    displace::{Diffed, Displaced},
+    input::RowIn,
+    types::Die,
+    udiff::{digest, DiffRange},
+  },
+  crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{read, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
+    terminal::{
+      disable_raw_mode, enable_raw_mode, size, Clear, ClearType, EnterAlternateScreen,
+      LeaveAlternateScreen,
+    },
+  },
+  futures::{
+    future::ready,
+    stream::{Stream, TryStreamExt},
+  },
+  std::{
+    collections::{HashMap, HashSet},
+    io::{self, stderr, Stderr, Write},
+    path::PathBuf,
+  },
+  tokio::task::spawn_blocking,
+};
+
+const HELP: &str = " space toggle · f file · a all · J/K scroll · enter apply · q quit";
+
+struct Hunk {
+  range: DiffRange,
+  picked: bool,
+  preview: Vec<String>,
+}
+
+struct File {
+  diffed: Diffed,
+  preview: Vec<String>,
+  hunks: Vec<Hunk>,
+}
+
+#[derive(Clone, Copy)]
+enum Row {
+  File(usize),
+  Hunk(usize, usize),
+}
+
+struct State {
+  files: Vec<File>,
+  rows: Vec<Row>,
+  cursor: usize,
+  top: usize,
+  scroll: usize,
+}
+
+struct Screen {
+  out: Stderr,
+}
+
+impl Drop for Screen {
+  fn drop(&mut self) {
+    let _ = execute!(self.out, Show, LeaveAlternateScreen);
+    let _ = disable_raw_mode();
+  }
+}
+
+fn io_err(e: &io::Error) -> Die {
+  Die::IO(PathBuf::from("/dev/tty"), e.kind())
+}
+
+fn lines(print: &str) -> Vec<String> {
+  print
+    .lines()
+    .map(|line| line.replace('\t', "    "))
+    .collect()
+}
+
+fn colour(line: &str) -> Color {
+  match line.chars().next() {
+    _ if line.starts_with("+++") || line.starts_with("---") => Color::White,
+    Some('+') => Color::Green,
+    Some('-') => Color::Red,
+    Some('@') => Color::Cyan,
+    _ => Color::Reset,
+  }
+}
+
+fn clip(line: &str, width: usize) -> String {
+  line.chars().take(width).collect()
+}
+
+impl State {
+  fn new(unified: usize, diffed: Vec<Diffed>) -> Self {
+    let mut files = Vec::new();
+    let mut rows = Vec::new();
+    for (f_idx, diffed) in diffed.into_iter().enumerate() {
+      let preview = lines(&diffed.render(unified, None).to_string_lossy());
+      let hunks = diffed
+        .units(unified)
+        .into_iter()
+        .map(|range| {
+          let one = HashSet::from([range.clone()]);
+          let print = diffed.render(unified, Some(&one));
+          Hunk {
+            range,
+            picked: true,
+            preview: lines(&print.to_string_lossy()),
+          }
+        })
+        .collect::<Vec<_>>();
+      rows.push(Row::File(f_idx));
+      rows.extend((0..hunks.len()).map(|h_idx| Row::Hunk(f_idx, h_idx)));
+      files.push(File {
+        diffed,
+        preview,
+        hunks,
+      });
+    }
+    Self {
+      files,
+      rows,
+      cursor: 0,
+      top: 0,
+      scroll: 0,
+    }
+  }
+
+  fn picked(&self) -> (usize, usize) {
+    let hunks = self.files.iter().flat_map(|file| &file.hunks);
+    hunks.fold((0, 0), |(picked, total), hunk| {
+      (picked + usize::from(hunk.picked), total + 1)
+    })
+  }
+
+  fn toggle_file(&mut self, f_idx: usize) {
+    let hunks = &mut self.files[f_idx].hunks;
+    let picked = !hunks.iter().all(|hunk| hunk.picked);
+    for hunk in hunks {
+      hunk.picked = picked;
+    }
+  }
+
+  fn toggle(&mut self) {
+    match self.rows[self.cursor] {
+      Row::File(f_idx) => self.toggle_file(f_idx),
+      Row::Hunk(f_idx, h_idx) => {
+        let hunk = &mut self.files[f_idx].hunks[h_idx];
+        hunk.picked = !hunk.picked;
+      }
+    }
+  }
+
+  fn toggle_all(&mut self) {
+    let picked = !self
+      .files
+      .iter()
+      .flat_map(|file| &file.hunks)
+      .all(|hunk| hunk.picked);
+    for hunk in self.files.iter_mut().flat_map(|file| &mut file.hunks) {
+      hunk.picked = picked;
+    }
+  }
+
+  fn file_of(&self) -> usize {
+    match self.rows[self.cursor] {
+      Row::File(f_idx) | Row::Hunk(f_idx, _) => f_idx,
+    }
+  }
+
+  fn preview(&self) -> &[String] {
+    match self.rows[self.cursor] {
+      Row::File(f_idx) => &self.files[f_idx].preview,
+      Row::Hunk(f_idx, h_idx) => &self.files[f_idx].hunks[h_idx].preview,
+    }
+  }
+
+  fn step(&mut self, up: bool, by: usize) {
+    let last = self.rows.len().saturating_sub(1);
+    self.cursor = if up {
+      self.cursor.saturating_sub(by)
+    } else {
+      (self.cursor + by).min(last)
+    };
+    self.scroll = 0;
+  }
+
+  fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+    let (width, height) = size()?;
+    let (width, height) = (usize::from(width), usize::from(height));
+    let body = height.saturating_sub(1);
+    let left = (width / 3).clamp(1, 48);
+    let right = width.saturating_sub(left + 1);
+
+    if self.cursor < self.top {
+      self.top = self.cursor;
+    } else if body > 0 && self.cursor >= self.top + body {
+      self.top = self.cursor + 1 - body;
+    }
+
+    queue!(out, Clear(ClearType::All))?;
+    for (y, (idx, row)) in self
+      .rows
+      .iter()
+      .enumerate()
+      .skip(self.top)
+      .take(body)
+      .enumerate()
+    {
+      let label = match *row {
+        Row::File(f_idx) => {
+          let hunks = &self.files[f_idx].hunks;
+          let mark = match hunks.iter().filter(|hunk| hunk.picked).count() {
+            0 => ' ',
+            n if n == hunks.len() => 'x',
+            _ => '~',
+          };
+          let name = self.files[f_idx].diffed.name.to_string_lossy();
+          format!("[{mark}] {name}")
+        }
+        Row::Hunk(f_idx, h_idx) => {
+          let hunk = &self.files[f_idx].hunks[h_idx];
+          let mark = if hunk.picked { 'x' } else { ' ' };
+          format!("  [{mark}] {}", hunk.range)
+        }
+      };
+      let y = u16::try_from(y).unwrap_or(u16::MAX);
+      queue!(out, MoveTo(0, y))?;
+      if idx == self.cursor {
+        queue!(out, SetAttribute(Attribute::Reverse))?;
+      }
+      queue!(
+        out,
+        Print(clip(&label, left)),
+        SetAttribute(Attribute::Reset)
+      )?;
+    }
+
+    let x = u16::try_from(left + 1).unwrap_or(u16::MAX);
+    for (y, line) in self
+      .preview()
+      .iter()
+      .skip(self.scroll)
+      .take(body)
+      .enumerate()
+    {
+      let y = u16::try_from(y).unwrap_or(u16::MAX);
+      queue!(
+        out,
+        MoveTo(x, y),
+        SetForegroundColor(colour(line)),
+        Print(clip(line, right)),
+        ResetColor
+      )?;
+    }
+
+    let (picked, total) = self.picked();
+    let status = format!(" {picked}/{total} hunks ·{HELP}");
+    let y = u16::try_from(body).unwrap_or(u16::MAX);
+    queue!(
+      out,
+      MoveTo(0, y),
+      SetAttribute(Attribute::Dim),
+      Print(clip(&status, width)),
+      SetAttribute(Attribute::Reset)
+    )?;
+    out.flush()
+  }
+
+  fn into_rows(self) -> Vec<RowIn> {
+    self
+      .files
+      .into_iter()
+      .filter_map(|file| {
+        let before = &file.diffed.before;
+        let picks = file
+          .hunks
+          .into_iter()
+          .filter(|hunk| hunk.picked)
+          .map(|hunk| {
+            let digest = digest(&hunk.range, before);
+            (hunk.range, digest)
+          })
+          .collect::<HashMap<_, _>>();
+        if picks.is_empty() {
+          None
+        } else {
+          Some(RowIn::Piecewise(file.diffed.path, picks, HashMap::new()))
+        }
+      })
+      .collect()
+  }
+}
+
+fn pick(unified: usize, diffed: Vec<Diffed>) -> Result<Vec<RowIn>, Die> {
+  let mut state = State::new(unified, diffed);
+  if state.rows.is_empty() {
+    return Ok(Vec::new());
+  }
+
+  enable_raw_mode().map_err(|e| io_err(&e))?;
+  let mut screen = Screen { out: stderr() };
+  execute!(screen.out, EnterAlternateScreen, Hide).map_err(|e| io_err(&e))?;
+
+  loop {
+    state.draw(&mut screen.out).map_err(|e| io_err(&e))?;
+    let Event::Key(KeyEvent {
+      code,
+      modifiers,
+      kind: KeyEventKind::Press | KeyEventKind::Repeat,
+      ..
+    }) = read().map_err(|e| io_err(&e))?
+    else {
+      continue;
+    };
+    let page = usize::from(size().map_err(|e| io_err(&e))?.1 / 2).max(1);
+    match (code, modifiers) {
+      (KeyCode::Char('c'), KeyModifiers::CONTROL) | (KeyCode::Char('q') | KeyCode::Esc, _) => {
+        return Err(Die::Interrupt)
+      }
+      (KeyCode::Enter, _) => break,
+      (KeyCode::Char('j') | KeyCode::Down, _) => state.step(false, 1),
+      (KeyCode::Char('k') | KeyCode::Up, _) => state.step(true, 1),
+      (KeyCode::PageDown, _) => state.step(false, page),
+      (KeyCode::PageUp, _) => state.step(true, page),
+      (KeyCode::Char('J'), _) => {
+        let max = state.preview().len().saturating_sub(1);
+        state.scroll = (state.scroll + page).min(max);
+      }
+      (KeyCode::Char('K'), _) => state.scroll = state.scroll.saturating_sub(page),
+      (KeyCode::Char(' '), _) => state.toggle(),
+      (KeyCode::Char('f'), _) => state.toggle_file(state.file_of()),
+      (KeyCode::Char('a'), _) => state.toggle_all(),
+      _ => (),
+    }
+  }
+
+  drop(screen);
+  Ok(state.into_rows())
+}
+
+pub async fn stream_pick(
+  unified: usize,
+  stream: impl Stream<Item = Result<Displaced, Die>>,
+) -> Result<Vec<RowIn>, Die> {
+  let diffed = stream
+    .try_filter_map(|displaced| {
+      ready(Ok(match displaced {
+        Displaced::Listed(_, diffed) => Some(diffed),
+        _ => None,
+      }))
+    })
+    .try_collect::<Vec<_>>()
+    .await?;
+  spawn_blocking(move || pick(unified, diffed))
+    .await
+    .map_err(|_| Die::Interrupt)?
+}
@@ -1,5 +1,6 @@
 use {
   aho_corasick::BuildError,
+  fancy_regex::Error as FancyError,
   regex::Error as RegexError,
   std::{
     clone::Clone,
@@ -16,16 +17,36 @@ pub enum Die {
   Interrupt,
   RegexError(RegexError),
   BuildError(BuildError),
+  FancyError(FancyError),
+  ScriptError(String),
   ArgumentError(String),
   IO(PathBuf, ErrorKind),
   BadExit(PathBuf, i32),
+  Stale(PathBuf, String),
+  Remote(String),
 }
 
 impl Error for Die {}
 
 impl Display for Die {
   fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-    write!(f, "Error: {self:?}")
+    match self {
+      Self::Eof => write!(f, "Error: EOF"),
+      Self::Interrupt => write!(f, "Error: Interrupted"),
+      Self::RegexError(e) => write!(f, "Error: Regex :: {e}"),
+      Self::BuildError(e) => write!(f, "Error: Aho-Corasick :: {e}"),
+      Self::FancyError(e) => write!(f, "Error: Regex :: {e}"),
+      Self::ScriptError(e) => write!(f, "Error: Script :: {e}"),
+      Self::ArgumentError(e) => write!(f, "Error: Argument :: {e}"),
+      Self::IO(path, kind) => write!(f, "Error: IO :: {kind} :: {}", path.display()),
+      Self::BadExit(path, code) => write!(f, "Error: Exit {code} :: {}", path.display()),
+      Self::Stale(path, hunk) => write!(
+        f,
+        "Error: Changed since preview, {hunk} not found :: {}",
+        path.display()
+      ),
+      Self::Remote(e) => write!(f, "{e}"),
+    }
   }
 }
 
@@ -35,6 +56,12 @@ impl From<RegexError> for Die {
   }
 }
 
+impl From<FancyError> for Die {
+  fn from(e: FancyError) -> Self {
+    Self::FancyError(e)
+  }
+}
+
 impl From<BuildError> for Die {
   fn from(e: BuildError) -> Self {
     Self::BuildError(e)
@@ -1,16 +1,25 @@
 use {
+  blake3::Hasher,
   difflib::sequencematcher::{Opcode, SequenceMatcher},
   std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     ffi::{OsStr, OsString},
     fmt::{self, Display, Formatter},
+    ops::Range,
   },
 };
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct DiffRange {
   pub before: (usize, usize),
   pub after: (usize, usize),
+  pub column: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Edit {
+  pub span: Range<usize>,
+  pub replacement: String,
 }
 
 impl DiffRange {
@@ -19,6 +28,7 @@ impl DiffRange {
       (Some(first), Some(last)) => Some(Self {
         before: (first.first_start, last.first_end - first.first_start),
         after: (first.second_start, last.second_end - first.second_start),
+        column: None,
       }),
       _ => None,
     }
@@ -30,13 +40,153 @@ impl Display for DiffRange {
     let (before_lo, before_hi) = (self.before.0 + 1, self.before.1);
     let (after_lo, after_hi) = (self.after.0 + 1, self.after.1);
 
-    write!(f, "@@ -{before_lo},{before_hi} +{after_lo},{after_hi} @@")
+    write!(f, "@@ -{before_lo},{before_hi} +{after_lo},{after_hi} @@")?;
+    self
+      .column
+      .map_or(Ok(()), |column| write!(f, " :{}", column + 1))
+  }
+}
+
+pub fn digest(range: &DiffRange, before: &[String]) -> String {
+  let mut hasher = Hasher::new();
+  for line in before.iter().skip(range.before.0).take(range.before.1) {
+    hasher.update(line.as_bytes());
+  }
+  if let Some(column) = range.column {
+    hasher.update(&column.to_le_bytes());
+  }
+  hasher.finalize().to_hex()[..16].to_owned()
+}
+
+pub fn p_edit(edited: &str, range: &DiffRange, before: &[String]) -> Option<Vec<String>> {
+  let mut old = String::new();
+  let mut new = Vec::new();
+  let mut in_hunk = false;
+
+  for line in edited.split_inclusive('\n') {
+    let body = line.get(1..).unwrap_or_default();
+    match line.chars().next() {
+      _ if line.starts_with('#') => (),
+      _ if line.starts_with("@@") => {
+        if in_hunk {
+          return None;
+        }
+        in_hunk = true;
+      }
+      _ if !in_hunk => (),
+      Some(' ') => {
+        old.push_str(body);
+        new.push(body.to_owned());
+      }
+      Some('-') => old.push_str(body),
+      Some('+') => new.push(body.to_owned()),
+      Some('\n') => {
+        old.push('\n');
+        new.push("\n".to_owned());
+      }
+      _ => return None,
+    }
+  }
+
+  let pre = before
+    .iter()
+    .skip(range.before.0)
+    .take(range.before.1)
+    .map(String::as_str)
+    .collect::<String>();
+  if !pre.ends_with('\n') && old.ends_with('\n') {
+    old.pop();
+    if let Some(last) = new.last_mut() {
+      if last.ends_with('\n') {
+        last.pop();
+      }
+    }
+  }
+  (in_hunk && old == pre).then_some(new)
+}
+
+pub fn relocate<'a>(
+  picks: &'a HashMap<DiffRange, String>,
+  ranges: Vec<DiffRange>,
+  before: &[String],
+) -> Result<HashSet<DiffRange>, &'a DiffRange> {
+  let mut candidates = ranges
+    .into_iter()
+    .map(|range| {
+      let digest = digest(&range, before);
+      (range, digest)
+    })
+    .collect::<Vec<_>>();
+  let mut ret = HashSet::new();
+
+  for (pick, want) in picks {
+    let found = candidates
+      .iter()
+      .enumerate()
+      .filter(|(_, (_, digest))| digest == want)
+      .min_by_key(|(_, (range, _))| range.before.0.abs_diff(pick.before.0))
+      .map(|(idx, _)| idx)
+      .ok_or(pick)?;
+    let (range, _) = candidates.swap_remove(found);
+    ret.insert(range);
+  }
+  Ok(ret)
+}
+
+pub fn apply_edits<'a>(text: &str, edits: impl IntoIterator<Item = &'a Edit>) -> String {
+  let mut ret = String::new();
+  let mut prev = 0;
+  for edit in edits {
+    ret.push_str(&text[prev..edit.span.start]);
+    ret.push_str(&edit.replacement);
+    prev = edit.span.end;
   }
+  ret.push_str(&text[prev..]);
+  ret
+}
+
+pub fn match_units(before: &[String], edits: &[Edit]) -> Vec<DiffRange> {
+  let mut offsets = Vec::with_capacity(before.len());
+  let mut acc = 0;
+  for line in before {
+    offsets.push(acc);
+    acc += line.len();
+  }
+  let line_of = |pos: usize| offsets.partition_point(|&o| o <= pos).saturating_sub(1);
+
+  edits
+    .iter()
+    .map(|edit| {
+      let lo = line_of(edit.span.start);
+      let hi = if edit.span.is_empty() {
+        lo
+      } else {
+        line_of(edit.span.end - 1)
+      };
+      let inc = before.len().min(hi + 1).saturating_sub(lo);
+      let start = offsets.get(lo).copied().unwrap_or_default();
+      let mut text = before
+        .iter()
+        .skip(lo)
+        .take(inc)
+        .map(String::as_str)
+        .collect::<String>();
+      text.replace_range(
+        edit.span.start - start..edit.span.end - start,
+        &edit.replacement,
+      );
+      DiffRange {
+        before: (lo, inc),
+        after: (lo, text.split_inclusive('\n').count()),
+        column: Some(edit.span.start - start),
+      }
+    })
+    .collect()
 }
 
 pub struct Patch<S> {
-  range: DiffRange,
-  new_lines: Vec<S>,
+  pub range: DiffRange,
+  pub new_lines: Vec<S>,
 }
 
 pub fn pure_diffs(unified: usize, before: &[String], after: &[String]) -> Vec<DiffRange> {
@@ -145,9 +295,9 @@ pub fn udiff(
       if !ranges.contains(&range) {
         continue;
       }
-    };
+    }
 
-    ret.push(&format!("{range}\n"));
+    ret.push(format!("{range}\n"));
 
     for code in group {
       if code.tag == "equal" {
@@ -1,10 +1,13 @@
 #[cfg(test)]
 mod spec {
-  use super::super::udiff::{apply_patches, patches, pure_diffs, udiff};
+  use super::super::udiff::{
+    apply_edits, apply_patches, digest, match_units, p_edit, patches, pure_diffs, relocate, udiff,
+    Edit,
+  };
   use difflib::unified_diff;
   use regex::Regex;
   use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fs::{read_dir, read_to_string},
     path::PathBuf,
   };
@@ -112,4 +115,106 @@ mod spec {
       assert_eq!(imp, canon);
     }
   }
+
+  #[test]
+  fn relocate_stale() {
+    let lines = |s: &str| {
+      s.split_inclusive('\n')
+        .map(String::from)
+        .collect::<Vec<_>>()
+    };
+    let text = "a\nfoo\nb\nc\nd\ne\nf\ng\nh\nfoo\n";
+    let before = lines(text);
+    let after = lines(&text.replace("foo", "bar"));
+    let picks = pure_diffs(1, &before, &after)
+      .into_iter()
+      .map(|range| {
+        let digest = digest(&range, &before);
+        (range, digest)
+      })
+      .collect::<HashMap<_, _>>();
+    assert_eq!(picks.len(), 2);
+
+    let shifted = format!("z\nz\n{text}");
+    let (before, after) = (lines(&shifted), lines(&shifted.replace("foo", "bar")));
+    let ranges = relocate(&picks, pure_diffs(1, &before, &after), &before).unwrap();
+    let patched = apply_patches(patches(1, &before, &after), &ranges, &before);
+    assert_eq!(patched.concat(), after.concat());
+
+    let edited = text.replace("b\n", "B\n");
+    let (before, after) = (lines(&edited), lines(&edited.replace("foo", "bar")));
+    assert!(relocate(&picks, pure_diffs(1, &before, &after), &before).is_err());
+  }
+
+  #[test]
+  fn edit_hunk() {
+    let lines = |s: &str| {
+      s.split_inclusive('\n')
+        .map(String::from)
+        .collect::<Vec<_>>()
+    };
+    let before = lines("a\nfoo\nb\n");
+    let after = lines("a\nbar\nb\n");
+    let ranges = pure_diffs(1, &before, &after);
+    let range = ranges.first().unwrap();
+    let hunk = udiff(
+      Some(&HashSet::from([range.clone()])),
+      1,
+      Default::default(),
+      &before,
+      &after,
+    )
+    .to_string_lossy()
+    .into_owned();
+
+    let ps = patches(1, &before, &after);
+    let patch = ps.first().unwrap();
+    assert_eq!(p_edit(&hunk, range, &before).unwrap(), patch.new_lines);
+
+    let tweaked = hunk.replace("+bar", "+baz");
+    assert_eq!(
+      p_edit(&tweaked, range, &before).unwrap().concat(),
+      "a\nbaz\nb\n"
+    );
+
+    let corrupt = hunk.replace(" b\n", " c\n");
+    assert!(p_edit(&corrupt, range, &before).is_none());
+    assert!(p_edit("no hunk here\n", range, &before).is_none());
+  }
+
+  #[test]
+  fn per_match() {
+    let text = "a\nfoo foo foo\nb\n";
+    let before = text
+      .split_inclusive('\n')
+      .map(String::from)
+      .collect::<Vec<_>>();
+    let edits = text
+      .match_indices("foo")
+      .map(|(idx, m)| Edit {
+        span: idx..idx + m.len(),
+        replacement: "bar".to_owned(),
+      })
+      .collect::<Vec<_>>();
+    let units = match_units(&before, &edits);
+    assert_eq!(
+      units.iter().map(|u| u.column).collect::<Vec<_>>(),
+      vec![Some(0), Some(4), Some(8)]
+    );
+    assert!(units
+      .iter()
+      .all(|u| u.before == (1, 1) && u.after == (1, 1)));
+    let digests = units
+      .iter()
+      .map(|u| digest(u, &before))
+      .collect::<HashSet<_>>();
+    assert_eq!(digests.len(), 3);
+
+    let picked = edits
+      .iter()
+      .zip(&units)
+      .filter(|(_, u)| u.column == Some(4))
+      .map(|(e, _)| e);
+    assert_eq!(apply_edits(text, picked), "a\nfoo bar foo\nb\n");
+  }
 }